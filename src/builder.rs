@@ -0,0 +1,140 @@
+use crate::command::Command;
+use crate::error::ConfigError;
+
+/// Assembles a [`Command`] step by step.
+///
+/// [`build`](CommandBuilder::build) checks the prefixes with [`Command::check_prefixes`],
+/// so the built command can always be displayed and parsed again.
+///
+/// ```
+/// use command_parser::{CommandBuilder, Parser};
+///
+/// let command = CommandBuilder::new('!', '-')
+///     .name("greet")
+///     .arg("Alice")
+///     .option("loud")
+///     .param("times", "3")
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(command, Parser::new('!', '-').parse("!greet Alice -loud -times:3").unwrap());
+/// ```
+#[derive(Debug, Clone)]
+pub struct CommandBuilder {
+    command: Command,
+}
+
+impl CommandBuilder {
+    pub fn new(prefix: char, option_prefix: char) -> Self {
+        CommandBuilder {
+            command: Command {
+                prefix,
+                option_prefix,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.command.name = name.into();
+        self
+    }
+
+    /// Appends an argument.
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.command.push_arg(arg);
+        self
+    }
+
+    /// Reserves capacity for at least `additional` more arguments.
+    pub fn args_reserve(mut self, additional: usize) -> Self {
+        self.command.arguments.reserve(additional);
+        self
+    }
+
+    /// Appends all arguments of `args` in order.
+    pub fn extend_args<I: IntoIterator<Item = String>>(mut self, args: I) -> Self {
+        self.command.arguments.extend(args);
+        self
+    }
+
+    pub fn option(mut self, opt: impl Into<String>) -> Self {
+        self.command.options.insert(opt.into());
+        self
+    }
+
+    /// Reserves capacity for at least `additional` more options.
+    pub fn options_reserve(mut self, additional: usize) -> Self {
+        self.command.options.reserve(additional);
+        self
+    }
+
+    pub fn extend_options<I: IntoIterator<Item = String>>(mut self, options: I) -> Self {
+        self.command.options.extend(options);
+        self
+    }
+
+    /// Sets a parameter, replacing any previous value of `key`.
+    pub fn param(mut self, key: impl Into<String>, val: impl Into<String>) -> Self {
+        self.command.set_param(key, val);
+        self
+    }
+
+    /// Reserves capacity for at least `additional` more parameters.
+    pub fn params_reserve(mut self, additional: usize) -> Self {
+        self.command.parameters.reserve(additional);
+        self
+    }
+
+    /// Sets all parameters of `params`, where later values replace earlier ones with the same key.
+    pub fn extend_params<I: IntoIterator<Item = (String, String)>>(mut self, params: I) -> Self {
+        for (key, val) in params {
+            self.command.set_param(key, val);
+        }
+
+        self
+    }
+
+    pub fn build(self) -> Result<Command, ConfigError> {
+        self.command.check_prefixes()?;
+        Ok(self.command)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejected_prefixes_test() {
+        assert_eq!(CommandBuilder::new('!', '!').build().unwrap_err(), ConfigError::PrefixesEqual('!'));
+        assert_eq!(CommandBuilder::new('!', '"').build().unwrap_err(), ConfigError::ReservedPrefix('"'));
+        assert_eq!(CommandBuilder::new(' ', '-').build().unwrap_err(), ConfigError::ReservedPrefix(' '));
+        assert_eq!(CommandBuilder::new('\\', '-').build().unwrap_err(), ConfigError::ReservedPrefix('\\'));
+        assert_eq!(CommandBuilder::new('!', '\'').build().unwrap_err(), ConfigError::ReservedPrefix('\''));
+        assert!(CommandBuilder::new('/', '+').name("foo").build().is_ok());
+    }
+
+    #[test]
+    fn extend_test() {
+        let command = CommandBuilder::new('!', '-')
+            .name("bulk")
+            .args_reserve(10_000)
+            .extend_args((0..10_000).map(|i| i.to_string()))
+            .arg("last")
+            .options_reserve(2)
+            .extend_options(["a".to_string(), "b".to_string()])
+            .params_reserve(2)
+            .extend_params([("k".to_string(), "1".to_string()), ("k".to_string(), "2".to_string())])
+            .build()
+            .unwrap();
+
+        assert_eq!(command.arguments.len(), 10_001);
+        assert_eq!(command.arg(9_999), Some("9999"));
+        assert_eq!(command.arg(10_000), Some("last"));
+        assert_eq!(command.options_sorted(), vec!["a", "b"]);
+        assert_eq!(command.parameters_sorted(), vec![("k", "2")]);
+        assert_eq!(crate::Parser::new('!', '-').parse(command.to_string()).unwrap(), command);
+    }
+}
@@ -0,0 +1,69 @@
+use crate::command::Command;
+
+/// Reconstructs an argv-style vector from `cmd` that can be passed to
+/// [`clap::Command::try_get_matches_from`].
+///
+/// The name comes first, followed by options as `--opt` and parameters as `--key=val`,
+/// both sorted by name. The arguments come last in order, after a `--`,
+/// so clap doesn't read an argument like `-4` as a flag.
+pub fn command_to_arg_vec(cmd: &Command) -> Vec<String> {
+    let mut argv = vec![cmd.name.clone()];
+
+    argv.extend(cmd.options_sorted().into_iter().map(|opt| format!("--{}", opt)));
+    argv.extend(cmd.parameters_sorted().into_iter().map(|(key, val)| format!("--{}={}", key, val)));
+
+    if !cmd.arguments.is_empty() {
+        argv.push("--".to_string());
+        argv.extend(cmd.arguments.iter().cloned());
+    }
+
+    argv
+}
+
+
+#[cfg(test)]
+mod tests {
+    use clap::{Arg, ArgAction};
+    use super::*;
+    use crate::parser::Parser;
+
+    #[test]
+    fn command_to_arg_vec_test() {
+        let p = Parser::new('!', '-');
+        let command = p.parse(r#"!greet Alice "Bob Jr." -loud -times:3 -delay:"5 s""#).unwrap();
+
+        let argv = command_to_arg_vec(&command);
+        assert_eq!(argv, vec!["greet", "--loud", "--delay=5 s", "--times=3", "--", "Alice", "Bob Jr."]);
+
+        let matches = clap::Command::new("greet")
+            .arg(Arg::new("names").num_args(1..))
+            .arg(Arg::new("loud").long("loud").action(ArgAction::SetTrue))
+            .arg(Arg::new("times").long("times"))
+            .arg(Arg::new("delay").long("delay"))
+            .try_get_matches_from(argv)
+            .unwrap();
+
+        assert!(matches.get_flag("loud"));
+        assert_eq!(matches.get_one::<String>("times").unwrap(), "3");
+        assert_eq!(matches.get_many::<String>("names").unwrap().collect::<Vec<_>>(), vec!["Alice", "Bob Jr."]);
+    }
+
+    #[test]
+    fn command_to_arg_vec_hyphen_test() {
+        let p = Parser::new('!', '-');
+        let command = p.parse(r#"!calc "-4" 3 -offset:"-2""#).unwrap();
+
+        let argv = command_to_arg_vec(&command);
+        assert_eq!(argv, vec!["calc", "--offset=-2", "--", "-4", "3"]);
+        assert_eq!(command_to_arg_vec(&p.parse("!calc -v").unwrap()), vec!["calc", "--v"]);
+
+        let matches = clap::Command::new("calc")
+            .arg(Arg::new("numbers").num_args(1..))
+            .arg(Arg::new("offset").long("offset"))
+            .try_get_matches_from(argv)
+            .unwrap();
+
+        assert_eq!(matches.get_one::<String>("offset").unwrap(), "-2");
+        assert_eq!(matches.get_many::<String>("numbers").unwrap().collect::<Vec<_>>(), vec!["-4", "3"]);
+    }
+}
@@ -1,17 +1,142 @@
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::mem::size_of;
+use std::num::{ParseFloatError, ParseIntError};
+use std::ops::Range;
+use crate::diff::CommandDiff;
+use crate::error::{CoerceError, ConfigError, MissingArg, ParseError, ValidationError};
+use crate::parser::{DuplicateMode, Parser};
+use crate::schema::{Schema, TypedCommand};
+use crate::spec::CommandSpec;
+use crate::tokenizer::{Token, Tokenizer};
 
 
+/// How a flag appears in a [`Command`], returned by [`Command::flag`].
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum FlagValue<'a> {
+    /// Neither an option nor a parameter with that name exists.
+    Absent,
+    /// The flag is an option.
+    Present,
+    /// The flag is a parameter with the given value.
+    WithValue(&'a str),
+}
+
+/// A setting that can be switched on or given a value, returned by [`Command::resolve`].
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Resolution<'a> {
+    /// Neither given as option nor as parameter, like `color` in `!draw`.
+    Off,
+    /// Given as option, like `-color`.
+    On,
+    /// Given as parameter, like `-color:red`.
+    Value(&'a str),
+}
+
+/// Quotes an argument or parameter value was written with.
+#[derive(Debug, Default, Eq, PartialEq, Hash, Copy, Clone)]
+pub enum QuoteStyle {
+    #[default]
+    Unquoted,
+    /// Enclosed by `"`.
+    Double,
+    /// Enclosed by `'`, see [`Parser::single_quotes`](crate::Parser::single_quotes).
+    Single,
+}
+
+/// Quote styles of the arguments and parameter values of a [`Command`].
+///
+/// Only used to reproduce the original quotes when displaying a command.
+/// Anything missing is treated as [`QuoteStyle::Unquoted`].
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct QuoteStyles {
+    /// Quote style of each argument, by index.
+    pub arguments: Vec<QuoteStyle>,
+    /// Quote style of quoted parameter values, by key.
+    pub parameters: HashMap<String, QuoteStyle>,
+}
+
+impl QuoteStyles {
+    /// Quote style of the argument at `index`.
+    pub fn argument(&self, index: usize) -> QuoteStyle {
+        self.arguments.get(index).copied().unwrap_or_default()
+    }
+
+    /// Quote style of the value of the parameter `key`.
+    pub fn parameter(&self, key: &str) -> QuoteStyle {
+        self.parameters.get(key).copied().unwrap_or_default()
+    }
+}
+
+/// Char ranges in the parsed string that the parts of a [`Command`] come from.
+///
+/// Created by [`Parser::parse_with_spans`](crate::Parser::parse_with_spans).
+/// Ranges include prefixes and quotes, a parameter's range covers both key and value.
+/// If an option or parameter appears multiple times, the last one is kept.
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct TokenSpans {
+    pub name: Range<usize>,
+    pub arguments: Vec<Range<usize>>,
+    pub options: HashMap<String, Range<usize>>,
+    pub parameters: HashMap<String, Range<usize>>,
+}
+
+impl TokenSpans {
+    pub(crate) fn push_token(&mut self, token: &Token, span: Range<usize>) {
+        match token {
+            Token::Name(_) => { self.name = span; }
+            Token::Argument(..) => { self.arguments.push(span); }
+            Token::Option(opt) => { self.options.insert(opt.to_string(), span); }
+            Token::Parameter(key, ..) => { self.parameters.insert(key.to_string(), span); }
+        }
+    }
+}
+
 /// Created from a string using a [`Parser`](crate::Parser).
 ///
 /// For more detail look at [`Parser`](crate::Parser) documentation.
-#[derive(Debug, Eq, PartialEq, Clone)]
+///
+/// Displaying a command produces a string that parses to an equal command again.
+/// Arguments and parameter values keep the quotes they were written with,
+/// anything else that needs quotes is enclosed by `"`.
+/// Options and parameters are written after the arguments, sorted by name.
+///
+/// How the command was written is kept in private fields, see [`quote_styles`](Command::quote_styles()),
+/// [`option_counts`](Command::option_counts()) and [`key_casing`](Command::key_casing()).
+/// Because of them a command is created with [`Command::new`] or a [`CommandBuilder`](crate::CommandBuilder)
+/// instead of a struct literal.
+///
+/// ```
+/// use command_parser::Parser;
+///
+/// let p = Parser::new('!', '-');
+/// let command = p.parse(r#"!foo -opt "long arg" -key:"long val" arg"#).unwrap();
+///
+/// assert_eq!(command.to_string(), r#"!foo "long arg" arg -opt -key:"long val""#);
+/// assert_eq!(p.parse(&command.to_string()).unwrap(), command);
+/// ```
+#[derive(Debug, Default, Clone)]
 pub struct Command {
     pub prefix: char,
     pub option_prefix: char,
     pub name: String,
     pub arguments: Vec<String>,
     pub options: HashSet<String>,
-    pub parameters: HashMap<String, String>
+    pub parameters: HashMap<String, String>,
+    /// How the arguments and parameter values were quoted.
+    ///
+    /// This is not compared when checking two commands for equality.
+    pub(crate) quote_styles: QuoteStyles,
+    /// How often each option was given.
+    ///
+    /// This is not compared when checking two commands for equality.
+    pub(crate) option_counts: HashMap<String, usize>,
+    /// How parameter keys were written, for keys that were lowercased by
+    /// [`Parser::case_insensitive_keys`](crate::Parser::case_insensitive_keys).
+    ///
+    /// This is used when displaying the command but not compared when checking two commands for equality.
+    pub(crate) key_casing: HashMap<String, String>,
 }
 
 impl Command {
@@ -29,8 +154,1437 @@ impl Command {
             name,
             arguments,
             options,
-            parameters
+            parameters,
+            quote_styles: QuoteStyles::default(),
+            option_counts: HashMap::new(),
+            key_casing: HashMap::new(),
+        }
+    }
+
+    /// Creates a command without arguments from a flag list as returned by [`Command::flags`].
+    ///
+    /// `(name, None)` becomes an option and `(key, Some(val))` a parameter.
+    /// A later parameter with the same key replaces an earlier one.
+    pub fn from_flags<I>(prefix: char, option_prefix: char, name: String, flags: I) -> Command
+    where
+        I: IntoIterator<Item = (String, Option<String>)>,
+    {
+        let mut command = Command::new(prefix, option_prefix, name, Vec::new(), HashSet::new(), HashMap::new());
+
+        for (name, val) in flags {
+            match val {
+                Some(val) => { command.parameters.insert(name, val); }
+                None => { command.options.insert(name); }
+            }
+        }
+
+        command
+    }
+
+    pub(crate) fn push_token(&mut self, token: Token, duplicate_option: DuplicateMode) {
+        match token {
+            Token::Name(name) => { self.name = name.into_owned(); }
+            Token::Argument(arg, style) => {
+                self.quote_styles.arguments.push(style);
+                self.arguments.push(arg.into_owned());
+            }
+            Token::Option(opt) => {
+                let opt = opt.into_owned();
+                let count = self.option_counts.entry(opt.clone()).or_default();
+                *count = if duplicate_option == DuplicateMode::Count { *count + 1 } else { 1 };
+                self.options.insert(opt);
+            }
+            Token::Parameter(key, val, style) => {
+                let key = key.into_owned();
+
+                if style == QuoteStyle::Unquoted {
+                    self.quote_styles.parameters.remove(&key);
+                } else {
+                    self.quote_styles.parameters.insert(key.clone(), style);
+                }
+
+                self.key_casing.remove(&key);
+
+                self.parameters.insert(key, val.into_owned());
+            }
+        }
+    }
+
+    /// How the arguments and parameter values were quoted.
+    pub fn quote_styles(&self) -> &QuoteStyles {
+        &self.quote_styles
+    }
+
+    /// How often each parsed option was given, see [`repeated_option_level`](Command::repeated_option_level).
+    pub fn option_counts(&self) -> &HashMap<String, usize> {
+        &self.option_counts
+    }
+
+    /// How parameter keys were written, for keys that were lowercased by
+    /// [`Parser::case_insensitive_keys`](crate::Parser::case_insensitive_keys).
+    pub fn key_casing(&self) -> &HashMap<String, String> {
+        &self.key_casing
+    }
+
+    /// Checks whether the command is called `name`.
+    pub fn is(&self, name: &str) -> bool {
+        self.name == name
+    }
+
+    /// Checks whether the command is called any of `names`.
+    pub fn is_any(&self, names: &[&str]) -> bool {
+        names.iter().any(|name| self.is(name))
+    }
+
+    /// Checks whether the command is called `name`, ignoring ASCII case.
+    pub fn is_ignore_case(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+    }
+
+    /// Checks whether the command is called any of `names`, ignoring ASCII case.
+    pub fn is_any_ignore_case(&self, names: &[&str]) -> bool {
+        names.iter().any(|name| self.is_ignore_case(name))
+    }
+
+    /// Removes the option `name`, returning whether it was present.
+    pub fn remove_option(&mut self, name: &str) -> bool {
+        self.option_counts.remove(name);
+        self.options.remove(name)
+    }
+
+    /// Renames the option `from` to `to`, returning whether `from` was present.
+    ///
+    /// If `to` is already set it stays as it is, including how often it was given.
+    pub fn alias_option(&mut self, from: &str, to: &str) -> bool {
+        if from == to {
+            return self.options.contains(from);
+        }
+
+        let count = self.option_counts.remove(from);
+
+        if !self.options.remove(from) {
+            return false;
+        }
+
+        if self.options.insert(to.to_string()) {
+            if let Some(count) = count {
+                self.option_counts.insert(to.to_string(), count);
+            }
+        }
+
+        true
+    }
+
+    /// How often the option `name` was given, so `-v -v -v` gives `3` for `v`.
+    ///
+    /// Repeats are only counted with [`DuplicateMode::Count`], otherwise a parsed option counts once.
+    /// Options that were inserted without being parsed count once.
+    pub fn repeated_option_level(&self, name: &str) -> usize {
+        match self.option_counts.get(name) {
+            Some(&count) if self.options.contains(name) => count,
+            _ => usize::from(self.options.contains(name)),
         }
     }
+
+    /// Keeps only the options for which `f` returns `true`.
+    pub fn retain_options<F: FnMut(&str) -> bool>(&mut self, mut f: F) {
+        self.options.retain(|opt| f(opt));
+
+        let options = &self.options;
+        self.option_counts.retain(|opt, _| options.contains(opt));
+    }
+
+    /// Keeps only the parameters for which `f` returns `true` given their key and value.
+    pub fn retain_parameters<F: FnMut(&str, &str) -> bool>(&mut self, mut f: F) {
+        self.parameters.retain(|key, val| f(key, val));
+
+        let parameters = &self.parameters;
+        self.quote_styles.parameters.retain(|key, _| parameters.contains_key(key));
+        self.key_casing.retain(|key, _| parameters.contains_key(key));
+    }
+
+    /// Renames the parameter `from` to `to` keeping its value, returning whether `from` was present.
+    ///
+    /// If `to` is already set the last write wins, so it takes the value of `from`.
+    pub fn rename_param(&mut self, from: &str, to: &str) -> bool {
+        if from == to {
+            return self.parameters.contains_key(from);
+        }
+
+        let val = match self.parameters.remove(from) {
+            Some(val) => val,
+            None => return false,
+        };
+
+        self.parameters.insert(to.to_string(), val);
+        self.key_casing.remove(from);
+        self.key_casing.remove(to);
+
+        match self.quote_styles.parameters.remove(from) {
+            Some(style) => { self.quote_styles.parameters.insert(to.to_string(), style); }
+            None => { self.quote_styles.parameters.remove(to); }
+        }
+
+        true
+    }
+
+    /// Sets the parameter `key` to `val`, returning the previous value if there was one.
+    ///
+    /// The new value is unquoted and the key is written as given, whatever the previous value was parsed from.
+    pub fn set_param(&mut self, key: impl Into<String>, val: impl Into<String>) -> Option<String> {
+        let key = key.into();
+
+        self.quote_styles.parameters.remove(&key);
+        self.key_casing.remove(&key);
+        self.parameters.insert(key, val.into())
+    }
+
+    /// Sets each parameter of `defaults` that isn't given yet, keeping the values that are.
+    ///
+    /// A parameter with an empty value, like `-key:`, counts as given.
+    pub fn apply_defaults(&mut self, defaults: &HashMap<String, String>) {
+        for (key, val) in defaults {
+            if !self.parameters.contains_key(key) {
+                self.parameters.insert(key.clone(), val.clone());
+            }
+        }
+    }
+
+    /// Appends `arg` to the arguments.
+    pub fn push_arg(&mut self, arg: impl Into<String>) {
+        self.arguments.push(arg.into());
+    }
+
+    /// The argument at `index`.
+    pub fn arg(&self, index: usize) -> Option<&str> {
+        self.arguments.get(index).map(String::as_str)
+    }
+
+    pub fn first_arg(&self) -> Option<&str> {
+        self.arg(0)
+    }
+
+    /// The arguments from `index` on, empty if there are fewer.
+    pub fn args_from(&self, index: usize) -> &[String] {
+        self.arguments.get(index..).unwrap_or_default()
+    }
+
+    /// Like [`arg`](Command::arg), but returns an error naming the index if it is missing.
+    ///
+    /// ```
+    /// use command_parser::{MissingArg, Parser};
+    ///
+    /// let command = Parser::new('!', '-').parse("!move a1").unwrap();
+    ///
+    /// assert_eq!(command.require_arg(0), Ok("a1"));
+    /// assert_eq!(command.require_arg(1), Err(MissingArg(1)));
+    /// ```
+    pub fn require_arg(&self, index: usize) -> Result<&str, MissingArg> {
+        self.arg(index).ok_or(MissingArg(index))
+    }
+
+    /// Maps the arguments to `names` by position, so `!move a1 b2` with `["from", "to"]` gives `from: a1` and `to: b2`.
+    ///
+    /// Arguments without a name are left out, as are names without an argument.
+    pub fn bind_positional<'n>(&self, names: &[&'n str]) -> HashMap<&'n str, &str> {
+        names.iter().copied().zip(self.arguments_iter()).collect()
+    }
+
+    /// Iterates over the arguments in order.
+    pub fn arguments_iter(&self) -> impl Iterator<Item = &str> {
+        self.arguments.iter().map(String::as_str)
+    }
+
+    /// Iterates over the options in no particular order.
+    pub fn options_iter(&self) -> impl Iterator<Item = &str> {
+        self.options.iter().map(String::as_str)
+    }
+
+    /// Iterates over the parameters as key-value pairs in no particular order.
+    pub fn parameters_iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.parameters.iter().map(|(key, val)| (key.as_str(), val.as_str()))
+    }
+
+    /// Iterates over the options starting with `prefix` in no particular order.
+    pub fn options_with_prefix(&self, prefix: &str) -> impl Iterator<Item = &str> {
+        // owned, so the items only borrow from the command
+        let prefix = prefix.to_owned();
+        self.options_iter().filter(move |opt| opt.starts_with(&prefix))
+    }
+
+    /// Iterates over the parameters whose key starts with `prefix` in no particular order.
+    pub fn parameters_with_prefix(&self, prefix: &str) -> impl Iterator<Item = (&str, &str)> {
+        let prefix = prefix.to_owned();
+        self.parameters_iter().filter(move |(key, _)| key.starts_with(&prefix))
+    }
+
+    /// Returns the options sorted lexicographically.
+    pub fn options_sorted(&self) -> Vec<&str> {
+        let mut options: Vec<&str> = self.options_iter().collect();
+        options.sort_unstable();
+        options
+    }
+
+    /// Returns the parameters as key-value pairs sorted by key.
+    pub fn parameters_sorted(&self) -> Vec<(&str, &str)> {
+        let mut parameters: Vec<(&str, &str)> = self.parameters_iter().collect();
+        parameters.sort_unstable_by_key(|(key, _)| *key);
+        parameters
+    }
+
+    /// Flattens the options and parameters into one list sorted by name.
+    ///
+    /// Options become `(name, None)` and parameters `(key, Some(val))`.
+    /// An option and a parameter with the same name are both kept, with the option first.
+    pub fn flags(&self) -> Vec<(String, Option<String>)> {
+        let mut flags: Vec<(String, Option<String>)> = self.options.iter()
+            .map(|opt| (opt.clone(), None))
+            .chain(self.parameters.iter().map(|(key, val)| (key.clone(), Some(val.clone()))))
+            .collect();
+        flags.sort_unstable();
+        flags
+    }
+
+    /// Borrows the name, arguments, options and parameters, leaving out the prefixes.
+    ///
+    /// Two commands that are [`semantic_eq`](Command::semantic_eq) have equal parts.
+    pub fn parts(&self) -> CommandParts<'_> {
+        CommandParts {
+            name: &self.name,
+            arguments: &self.arguments,
+            options: &self.options,
+            parameters: &self.parameters,
+        }
+    }
+
+    /// Returns the number of options plus the number of parameters.
+    pub fn options_and_params_len(&self) -> usize {
+        self.options.len() + self.parameters.len()
+    }
+
+    /// Roughly estimates how many bytes this command has allocated on the heap.
+    ///
+    /// Counts the bytes of all strings plus the slots of the containers holding them,
+    /// including [`quote_styles`](Command::quote_styles()), [`option_counts`](Command::option_counts())
+    /// and [`key_casing`](Command::key_casing()).
+    /// Hash table control bytes and allocator overhead are not included.
+    pub fn approx_heap_bytes(&self) -> usize {
+        let string = size_of::<String>();
+
+        let arguments = self.arguments.capacity() * string
+            + self.arguments.iter().map(String::capacity).sum::<usize>();
+        let options = self.options.capacity() * string
+            + self.options.iter().map(String::capacity).sum::<usize>();
+        let parameters = self.parameters.capacity() * 2 * string
+            + self.parameters.iter().map(|(key, val)| key.capacity() + val.capacity()).sum::<usize>();
+        let option_counts = self.option_counts.capacity() * (string + size_of::<usize>())
+            + self.option_counts.keys().map(String::capacity).sum::<usize>();
+        let quote_styles = self.quote_styles.arguments.capacity() * size_of::<QuoteStyle>()
+            + self.quote_styles.parameters.capacity() * (string + size_of::<QuoteStyle>())
+            + self.quote_styles.parameters.keys().map(String::capacity).sum::<usize>();
+        let key_casing = self.key_casing.capacity() * 2 * string
+            + self.key_casing.iter().map(|(key, original)| key.capacity() + original.capacity()).sum::<usize>();
+
+        self.name.capacity() + arguments + options + parameters + option_counts + quote_styles + key_casing
+    }
+
+    /// Returns the value of the parameter `key`, ignoring case.
+    ///
+    /// An exact match is preferred. Otherwise, if several keys only differ in case, any of them may be returned.
+    pub fn param_ci(&self, key: &str) -> Option<&str> {
+        if let Some(val) = self.parameters.get(key) {
+            return Some(val);
+        }
+
+        let key = key.to_lowercase();
+        self.parameters_iter().find(|(k, _)| k.to_lowercase() == key).map(|(_, val)| val)
+    }
+
+    /// Splits the value of the parameter `key` on `sep`.
+    ///
+    /// With `skip_empty` empty entries are dropped, so `a,,b,` gives `["a", "b"]` and an empty value gives an empty list.
+    /// Otherwise they are kept, so the same value gives `["a", "", "b", ""]` and an empty value gives `[""]`.
+    /// Returns `None` if there is no such parameter.
+    pub fn param_list(&self, key: &str, sep: char, skip_empty: bool) -> Option<Vec<&str>> {
+        self.parameters.get(key).map(|val| {
+            val.split(sep).filter(|entry| !skip_empty || !entry.is_empty()).collect()
+        })
+    }
+
+    /// Replaces `{N}` in arguments and parameter values with the `N`th argument.
+    ///
+    /// Placeholders refer to the arguments as they were before expanding, unknown indices are left as they are.
+    /// `{{` and `}}` produce literal braces.
+    ///
+    /// ```
+    /// use command_parser::Parser;
+    ///
+    /// let p = Parser::new('!', '-');
+    /// let mut command = p.parse(r#"!greet Alice -msg:"Hello {0}""#).unwrap();
+    /// command.expand_templates();
+    ///
+    /// assert_eq!(command.parameters.get("msg").unwrap(), "Hello Alice");
+    /// ```
+    pub fn expand_templates(&mut self) {
+        let arguments = self.arguments.clone();
+
+        for arg in &mut self.arguments {
+            *arg = expand_template(arg, &arguments);
+        }
+
+        for val in self.parameters.values_mut() {
+            *val = expand_template(val, &arguments);
+        }
+    }
+
+    /// Parses every argument as `f64`.
+    ///
+    /// Returns the index and error of the first argument that isn't a number.
+    pub fn args_as_f64(&self) -> Result<Vec<f64>, (usize, ParseFloatError)> {
+        self.arguments.iter()
+            .enumerate()
+            .map(|(i, arg)| arg.parse().map_err(|e| (i, e)))
+            .collect()
+    }
+
+    /// Parses every argument as `i64`.
+    ///
+    /// Returns the index and error of the first argument that isn't an integer.
+    pub fn args_as_i64(&self) -> Result<Vec<i64>, (usize, ParseIntError)> {
+        self.arguments.iter()
+            .enumerate()
+            .map(|(i, arg)| arg.parse().map_err(|e| (i, e)))
+            .collect()
+    }
+
+    /// Looks up `name` in both options and parameters.
+    ///
+    /// If `name` is both an option and a parameter key, the parameter takes precedence.
+    /// An explicitly empty value like `-name:""` gives `WithValue("")`, which is different from a missing parameter.
+    ///
+    /// ```
+    /// use command_parser::{FlagValue, Parser};
+    ///
+    /// let p = Parser::new('!', '-');
+    /// let command = p.parse(r#"!edit -name:"""#).unwrap();
+    ///
+    /// assert_eq!(command.flag("name"), FlagValue::WithValue(""));
+    /// assert_eq!(command.flag("title"), FlagValue::Absent);
+    /// ```
+    pub fn flag(&self, name: &str) -> FlagValue<'_> {
+        if let Some(val) = self.parameters.get(name) {
+            FlagValue::WithValue(val)
+        } else if self.options.contains(name) {
+            FlagValue::Present
+        } else {
+            FlagValue::Absent
+        }
+    }
+
+    /// Resolves a setting that is either switched on as option or given a value as parameter.
+    ///
+    /// The parameter takes precedence, like in [`flag`](Command::flag).
+    ///
+    /// ```
+    /// use command_parser::{Parser, Resolution};
+    ///
+    /// let p = Parser::new('!', '-');
+    ///
+    /// assert_eq!(p.parse("!draw -color:red").unwrap().resolve("color"), Resolution::Value("red"));
+    /// assert_eq!(p.parse("!draw -color").unwrap().resolve("color"), Resolution::On);
+    /// ```
+    pub fn resolve(&self, name: &str) -> Resolution<'_> {
+        match self.flag(name) {
+            FlagValue::Absent => Resolution::Off,
+            FlagValue::Present => Resolution::On,
+            FlagValue::WithValue(val) => Resolution::Value(val),
+        }
+    }
+
+    /// Formats the command as a multi-line summary for debugging.
+    ///
+    /// Unlike [`Display`](fmt::Display) the output is not meant to be parsed again.
+    ///
+    /// ```
+    /// use command_parser::Parser;
+    ///
+    /// let command = Parser::new('!', '-').parse(r#"!foo "long arg" -opt -key:val"#).unwrap();
+    ///
+    /// assert_eq!(command.pretty(), "name: foo\narguments:\n  0: long arg\noptions:\n  opt\nparameters:\n  key: val\n");
+    /// ```
+    pub fn pretty(&self) -> String {
+        let mut pretty = format!("name: {}\n", self.name);
+
+        pretty.push_str("arguments:\n");
+        for (i, arg) in self.arguments.iter().enumerate() {
+            pretty.push_str(&format!("  {}: {}\n", i, arg));
+        }
+
+        pretty.push_str("options:\n");
+        for opt in self.options_sorted() {
+            pretty.push_str(&format!("  {}\n", opt));
+        }
+
+        pretty.push_str("parameters:\n");
+        for (key, val) in self.parameters_sorted() {
+            pretty.push_str(&format!("  {}: {}\n", key, val));
+        }
+
+        pretty
+    }
+
+    /// Converts the arguments and parameters to the types declared in `schema`.
+    ///
+    /// Arguments and parameters that aren't declared are left out.
+    /// Returns the first argument or parameter that is missing or can't be parsed, arguments first.
+    pub fn coerce(&self, schema: &Schema) -> Result<TypedCommand, CoerceError> {
+        let mut typed = TypedCommand {
+            name: self.name.clone(),
+            ..Default::default()
+        };
+
+        for (i, coercer) in schema.arguments.iter().enumerate() {
+            let arg = self.arguments.get(i).ok_or(CoerceError::MissingArgument(i))?;
+            typed.arguments.push(coercer(arg).map_err(|e| CoerceError::InvalidArgument(i, e))?);
+        }
+
+        let mut keys: Vec<&String> = schema.parameters.keys().collect();
+        keys.sort_unstable();
+
+        for key in keys {
+            if let Some(val) = self.parameters.get(key) {
+                let val = schema.parameters[key](val).map_err(|e| CoerceError::InvalidParameter(key.clone(), e))?;
+                typed.parameters.insert(key.clone(), val);
+            }
+        }
+
+        Ok(typed)
+    }
+
+    /// Checks that the prefixes of the command can be displayed and parsed again.
+    ///
+    /// They have to differ from each other and can't be a space, a quote or `\`.
+    ///
+    /// ```
+    /// use command_parser::{Command, ConfigError};
+    ///
+    /// let mut command = Command::default();
+    /// command.prefix = '!';
+    /// command.option_prefix = '-';
+    /// assert!(command.check_prefixes().is_ok());
+    ///
+    /// command.option_prefix = '!';
+    /// assert_eq!(command.check_prefixes(), Err(ConfigError::PrefixesEqual('!')));
+    /// ```
+    pub fn check_prefixes(&self) -> Result<(), ConfigError> {
+        if let Some(&c) = [self.prefix, self.option_prefix].iter().find(|c| [' ', '"', '\'', '\\'].contains(c)) {
+            return Err(ConfigError::ReservedPrefix(c));
+        }
+
+        if self.prefix == self.option_prefix {
+            return Err(ConfigError::PrefixesEqual(self.prefix));
+        }
+
+        Ok(())
+    }
+
+    /// Lists what changed from this command to `other`.
+    ///
+    /// ```
+    /// use command_parser::Parser;
+    ///
+    /// let p = Parser::new('!', '-');
+    /// let old = p.parse("!foo -key:1").unwrap();
+    /// let new = p.parse("!foo -key:2 -opt").unwrap();
+    /// let diff = old.diff(&new);
+    ///
+    /// assert_eq!(diff.added_options, vec!["opt"]);
+    /// assert_eq!(diff.changed_parameters, vec![("key".to_string(), "1".to_string(), "2".to_string())]);
+    /// ```
+    pub fn diff(&self, other: &Command) -> CommandDiff {
+        CommandDiff::new(self, other)
+    }
+
+    /// Checks that the command matches `spec`, returning the first violation found.
+    ///
+    /// Argument counts are checked first, then required parameters in the order they are listed in `spec`,
+    /// then options in lexicographic order.
+    pub fn validate_against(&self, spec: &CommandSpec) -> Result<(), ValidationError> {
+        if self.arguments.len() < spec.min_args {
+            return Err(ValidationError::TooFewArguments(spec.min_args, self.arguments.len()));
+        }
+
+        if let Some(max_args) = spec.max_args {
+            if self.arguments.len() > max_args {
+                return Err(ValidationError::TooManyArguments(max_args, self.arguments.len()));
+            }
+        }
+
+        if let Some(key) = spec.required_params.iter().find(|key| !self.parameters.contains_key(*key)) {
+            return Err(ValidationError::MissingParameter(key.clone()));
+        }
+
+        if let Some(option) = self.options_sorted().into_iter().find(|option| !spec.allowed_options.contains(*option)) {
+            return Err(ValidationError::UnknownOption(option.to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+/// The parts of a [`Command`] without its prefixes, borrowed from it.
+///
+/// Created by [`Command::parts`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct CommandParts<'a> {
+    pub name: &'a str,
+    pub arguments: &'a [String],
+    pub options: &'a HashSet<String>,
+    pub parameters: &'a HashMap<String, String>,
 }
 
+/// A command of which only the name is parsed yet.
+///
+/// Created by [`Parser::parse_shallow`](crate::Parser::parse_shallow).
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct ShallowCommand {
+    pub name: String,
+    /// Everything after the space following the name, not interpreted in any way.
+    pub tail: String,
+}
+
+impl ShallowCommand {
+    /// Parses the tail into the arguments, options and parameters of a [`Command`] with this name.
+    ///
+    /// Gives the same result as parsing the whole input with `parser` directly,
+    /// as long as it's the parser that created this.
+    pub fn parse_tail(&self, parser: &Parser) -> Result<Command, ParseError> {
+        let mut command = parser.build(Tokenizer::fragment(parser, &self.tail))?;
+        command.name = self.name.clone();
+        Ok(command)
+    }
+}
+
+/// Like [`Command`], but borrows its text from the parsed string where possible.
+///
+/// Created by [`Parser::parse_cow`](crate::Parser::parse_cow).
+/// A token is only copied if its text isn't a contiguous part of the input,
+/// for example when it contains an escape sequence.
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct CommandCow<'a> {
+    pub prefix: char,
+    pub option_prefix: char,
+    pub name: Cow<'a, str>,
+    pub arguments: Vec<Cow<'a, str>>,
+    pub options: HashSet<Cow<'a, str>>,
+    pub parameters: HashMap<Cow<'a, str>, Cow<'a, str>>,
+}
+
+impl<'a> CommandCow<'a> {
+    pub(crate) fn push_token(&mut self, token: Token<'a>) {
+        match token {
+            Token::Name(name) => { self.name = name; }
+            Token::Argument(arg, _) => { self.arguments.push(arg); }
+            Token::Option(opt) => { self.options.insert(opt); }
+            Token::Parameter(key, val, _) => { self.parameters.insert(key, val); }
+        }
+    }
+
+    /// Converts into a [`Command`], copying any borrowed text.
+    pub fn into_owned(self) -> Command {
+        Command::new(
+            self.prefix,
+            self.option_prefix,
+            self.name.into_owned(),
+            self.arguments.into_iter().map(Cow::into_owned).collect(),
+            self.options.into_iter().map(Cow::into_owned).collect(),
+            self.parameters.into_iter().map(|(key, val)| (key.into_owned(), val.into_owned())).collect()
+        )
+    }
+}
+
+
+impl PartialEq for Command {
+    fn eq(&self, other: &Self) -> bool {
+        self.prefix == other.prefix &&
+            self.option_prefix == other.option_prefix &&
+            self.name == other.name &&
+            self.arguments == other.arguments &&
+            self.options == other.options &&
+            self.parameters == other.parameters
+    }
+}
+
+impl Eq for Command {}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_with(f, self.prefix, self.option_prefix)
+    }
+}
+
+impl Command {
+    /// Like [`to_string`](ToString::to_string), but uses `prefix` and `option_prefix` instead of the stored prefixes.
+    ///
+    /// ```
+    /// use command_parser::Parser;
+    ///
+    /// let command = Parser::new('!', '-').parse("!foo arg -opt").unwrap();
+    ///
+    /// assert_eq!(command.to_string_with('/', '+'), "/foo arg +opt");
+    /// ```
+    pub fn to_string_with(&self, prefix: char, option_prefix: char) -> String {
+        let mut s = String::new();
+        self.write_with(&mut s, prefix, option_prefix).expect("writing to a String can't fail");
+        s
+    }
+
+    /// Checks whether both commands are equal, ignoring their prefixes.
+    pub fn semantic_eq(&self, other: &Command) -> bool {
+        self.name == other.name &&
+            self.arguments == other.arguments &&
+            self.options == other.options &&
+            self.parameters == other.parameters
+    }
+
+    /// Writes the command in a form that only depends on what [`semantic_eq`](Command::semantic_eq) compares.
+    ///
+    /// The name comes first, followed by the sorted options, the sorted parameters and then the arguments in order.
+    /// It always uses `!` and `-` as prefixes, encloses tokens by `"` only where needed
+    /// and ignores [`quote_styles`](Command::quote_styles()) and [`key_casing`](Command::key_casing()).
+    ///
+    /// ```
+    /// use command_parser::Parser;
+    ///
+    /// let a = Parser::new('!', '-').parse(r#"!foo "x y" -b -a:1 z"#);
+    /// let b = Parser::new('/', '+').parse(r#"/foo +a:"1" "x y" z +b"#);
+    ///
+    /// assert_eq!(a.unwrap().canonical_string(), r#"!foo -b -a:1 "x y" z"#);
+    /// assert_eq!(b.unwrap().canonical_string(), r#"!foo -b -a:1 "x y" z"#);
+    /// ```
+    pub fn canonical_string(&self) -> String {
+        let mut s = String::new();
+        self.write_name(&mut s, '!', '-')
+            .and_then(|_| self.write_flags(&mut s, '-', false))
+            .and_then(|_| self.write_arguments(&mut s, '-', false))
+            .expect("writing to a String can't fail");
+        s
+    }
+
+    /// Writes the command without its prefix and option prefixes, for showing it to people.
+    ///
+    /// Tokens containing spaces are enclosed by `"`. Options and parameters come after the arguments, sorted by name.
+    /// Unlike [`to_string`](ToString::to_string) the result generally can't be parsed again.
+    ///
+    /// ```
+    /// use command_parser::Parser;
+    ///
+    /// let command = Parser::new('!', '-').parse(r#"!foo arg1 "arg 2" -opt -key:val"#).unwrap();
+    ///
+    /// assert_eq!(command.display_plain(), r#"foo arg1 "arg 2" opt key:val"#);
+    /// ```
+    pub fn display_plain(&self) -> String {
+        let quote = |token: &str| {
+            let mut s = String::new();
+            write_token(&mut s, token, QuoteStyle::Unquoted, token.contains(' ')).expect("writing to a String can't fail");
+            s
+        };
+
+        let mut parts = vec![quote(&self.name)];
+        parts.extend(self.arguments_iter().map(quote));
+        parts.extend(self.options_sorted().into_iter().map(quote));
+        parts.extend(self.parameters_sorted().into_iter().map(|(key, val)| format!("{}:{}", quote(key), quote(val))));
+        parts.join(" ")
+    }
+
+    fn write_with(&self, f: &mut impl fmt::Write, prefix: char, option_prefix: char) -> fmt::Result {
+        self.write_name(f, prefix, option_prefix)?;
+        self.write_arguments(f, option_prefix, true)?;
+        self.write_flags(f, option_prefix, true)
+    }
+
+    fn write_name(&self, f: &mut impl fmt::Write, prefix: char, option_prefix: char) -> fmt::Result {
+        let name_needs_quotes = self.name.contains([' ', prefix, option_prefix]) ||
+            self.name.contains(['"', '\'', '\\']);
+
+        write!(f, "{}", prefix)?;
+        write_token(f, &self.name, QuoteStyle::Unquoted, name_needs_quotes)
+    }
+
+    /// Writes the arguments, keeping their quote styles if `keep_styles` is set.
+    fn write_arguments(&self, f: &mut impl fmt::Write, option_prefix: char, keep_styles: bool) -> fmt::Result {
+        for (i, arg) in self.arguments.iter().enumerate() {
+            let needs_quotes = arg.is_empty() || arg.contains(' ') ||
+                starts_with_quote(arg) || arg.starts_with(option_prefix);
+            let style = if keep_styles { self.quote_styles.argument(i) } else { QuoteStyle::Unquoted };
+
+            f.write_str(" ")?;
+            write_token(f, arg, style, needs_quotes)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the options and parameters, keeping the quote styles of values and the casing of keys if `keep_styles` is set.
+    fn write_flags(&self, f: &mut impl fmt::Write, option_prefix: char, keep_styles: bool) -> fmt::Result {
+        for opt in self.options_sorted() {
+            write!(f, " {}", option_prefix)?;
+            write_key(f, opt)?;
+        }
+
+        for (key, val) in self.parameters_sorted() {
+            let needs_quotes = val.contains(' ') || starts_with_quote(val);
+
+            write!(f, " {}", option_prefix)?;
+
+            if keep_styles {
+                write_key(f, self.key_casing.get(key).map_or(key, String::as_str))?;
+                f.write_str(":")?;
+                write_token(f, val, self.quote_styles.parameter(key), needs_quotes)?;
+            } else {
+                write_key(f, key)?;
+                f.write_str(":")?;
+                write_token(f, val, QuoteStyle::Unquoted, needs_quotes)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn expand_template(template: &str, arguments: &[String]) -> String {
+    let mut expanded = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                expanded.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                expanded.push('}');
+            }
+            '{' => {
+                let mut index = String::new();
+
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    index.push(digit);
+                }
+
+                let arg = match chars.peek() {
+                    Some('}') => index.parse::<usize>().ok().and_then(|i| arguments.get(i)),
+                    _ => None,
+                };
+
+                match arg {
+                    Some(arg) => {
+                        chars.next();
+                        expanded.push_str(arg);
+                    }
+                    None => {
+                        expanded.push('{');
+                        expanded.push_str(&index);
+                    }
+                }
+            }
+            _ => {
+                expanded.push(c);
+            }
+        }
+    }
+
+    expanded
+}
+
+fn starts_with_quote(token: &str) -> bool {
+    token.starts_with(['"', '\''])
+}
+
+fn write_key(f: &mut impl fmt::Write, key: &str) -> fmt::Result {
+    let needs_quotes = key.contains([' ', ':', '=']) || starts_with_quote(key);
+    write_token(f, key, QuoteStyle::Unquoted, needs_quotes)
+}
+
+/// Writes `token` enclosed by the quotes of `style`, or by `"` if it's unquoted but `needs_quotes` is set.
+fn write_token(f: &mut impl fmt::Write, token: &str, style: QuoteStyle, needs_quotes: bool) -> fmt::Result {
+    let quote = match style {
+        QuoteStyle::Unquoted if !needs_quotes => return f.write_str(token),
+        QuoteStyle::Unquoted | QuoteStyle::Double => '"',
+        QuoteStyle::Single => '\'',
+    };
+
+    write!(f, "{}", quote)?;
+
+    for c in token.chars() {
+        if c == quote || c == '\\' {
+            write!(f, "\\")?;
+        }
+
+        write!(f, "{}", c)?;
+    }
+
+    write!(f, "{}", quote)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command() -> Command {
+        Command::new(
+            '!',
+            '-',
+            "foo".to_string(),
+            vec!["arg1".to_string()],
+            HashSet::from(["opt".to_string()]),
+            HashMap::from([("key".to_string(), "val".to_string())])
+        )
+    }
+
+    #[test]
+    fn remove_option_test() {
+        let mut command = command();
+
+        assert!(command.remove_option("opt"));
+        assert!(!command.remove_option("opt"));
+        assert!(command.options.is_empty());
+    }
+
+    #[test]
+    fn set_param_test() {
+        let mut command = command();
+
+        assert_eq!(command.set_param("key", "new"), Some("val".to_string()));
+        assert_eq!(command.set_param("other", "val".to_string()), None);
+        assert_eq!(command.parameters.get("key"), Some(&"new".to_string()));
+        assert_eq!(command.parameters.get("other"), Some(&"val".to_string()));
+
+        let mut p = crate::Parser::new('!', '-');
+        p.case_insensitive_keys = true;
+
+        let mut command = p.parse(r#"!foo -Name:"a" -k:"b c""#).unwrap();
+        command.set_param("name", "x");
+        command.set_param("k", "d");
+        assert_eq!(command.quote_styles.parameter("k"), QuoteStyle::Unquoted);
+        assert!(command.key_casing.is_empty());
+        assert_eq!(command.to_string(), "!foo -k:d -name:x");
+    }
+
+    #[test]
+    fn push_arg_test() {
+        let mut command = command();
+
+        command.push_arg("arg2");
+        command.push_arg("arg3".to_string());
+        assert_eq!(command.arguments, vec!["arg1", "arg2", "arg3"]);
+    }
+
+    #[test]
+    fn sorted_test() {
+        let mut command = command();
+        command.options.extend(["b".to_string(), "a".to_string(), "z".to_string()]);
+        command.set_param("a", "1");
+        command.set_param("z", "2");
+
+        assert_eq!(command.options_sorted(), vec!["a", "b", "opt", "z"]);
+        assert_eq!(command.parameters_sorted(), vec![("a", "1"), ("key", "val"), ("z", "2")]);
+    }
+
+    fn spec() -> CommandSpec {
+        CommandSpec {
+            required_params: vec!["key".to_string()],
+            allowed_options: HashSet::from(["opt".to_string()]),
+            min_args: 1,
+            max_args: Some(2),
+        }
+    }
+
+    #[test]
+    fn validate_against_test() {
+        assert_eq!(command().validate_against(&spec()), Ok(()));
+
+        let mut missing = command();
+        missing.parameters.clear();
+        assert_eq!(missing.validate_against(&spec()), Err(ValidationError::MissingParameter("key".to_string())));
+
+        let mut unknown = command();
+        unknown.options.insert("other".to_string());
+        assert_eq!(unknown.validate_against(&spec()), Err(ValidationError::UnknownOption("other".to_string())));
+
+        let mut too_few = command();
+        too_few.arguments.clear();
+        assert_eq!(too_few.validate_against(&spec()), Err(ValidationError::TooFewArguments(1, 0)));
+
+        let mut too_many = command();
+        too_many.push_arg("arg2");
+        too_many.push_arg("arg3");
+        assert_eq!(too_many.validate_against(&spec()), Err(ValidationError::TooManyArguments(2, 3)));
+    }
+
+    #[test]
+    fn flag_test() {
+        let mut command = command();
+        assert_eq!(command.flag("opt"), FlagValue::Present);
+        assert_eq!(command.flag("key"), FlagValue::WithValue("val"));
+        assert_eq!(command.flag("other"), FlagValue::Absent);
+
+        command.options.insert("key".to_string());
+        assert_eq!(command.flag("key"), FlagValue::WithValue("val"));
+    }
+
+    #[test]
+    fn resolve_test() {
+        let p = crate::Parser::new('!', '-');
+
+        assert_eq!(p.parse("!draw -color").unwrap().resolve("color"), Resolution::On);
+        assert_eq!(p.parse("!draw -color:red").unwrap().resolve("color"), Resolution::Value("red"));
+        assert_eq!(p.parse("!draw -color -color:red").unwrap().resolve("color"), Resolution::Value("red"));
+        assert_eq!(p.parse("!draw -size:3").unwrap().resolve("color"), Resolution::Off);
+    }
+
+    #[test]
+    fn empty_value_test() {
+        let p = crate::Parser::new('!', '-');
+
+        let command = p.parse(r#"!edit -name:"" -title:"#).unwrap();
+        assert_eq!(command.parameters.get("name"), Some(&String::new()));
+        assert_eq!(command.parameters.get("title"), Some(&String::new()));
+        assert_eq!(command.flag("name"), FlagValue::WithValue(""));
+        assert!(!command.options.contains("name"));
+
+        let command = p.parse("!edit").unwrap();
+        assert_eq!(command.parameters.get("name"), None);
+        assert_eq!(command.flag("name"), FlagValue::Absent);
+
+        let command = p.parse(r#"!edit -name:"""#).unwrap();
+        assert_eq!(p.parse(command.to_string()).unwrap().flag("name"), FlagValue::WithValue(""));
+    }
+
+    #[test]
+    fn display_test() {
+        assert_eq!(command().to_string(), "!foo arg1 -opt -key:val");
+
+        let mut command = command();
+        command.push_arg("long arg");
+        command.push_arg("");
+        command.push_arg("-arg");
+        command.push_arg(r#"a"b\c"#);
+        command.options.insert("long opt".to_string());
+        command.set_param("k:v", r#""val""#);
+
+        assert_eq!(
+            command.to_string(),
+            r#"!foo arg1 "long arg" "" "-arg" a"b\c -"long opt" -opt -"k:v":"\"val\"" -key:val"#
+        );
+        assert_eq!(crate::Parser::new('!', '-').parse(command.to_string()).unwrap(), command);
+    }
+
+    #[test]
+    fn display_name_test() {
+        let p = crate::Parser::new('!', '-');
+
+        let mut command = command();
+        command.name = "two words".to_string();
+        assert_eq!(command.to_string(), r#"!"two words" arg1 -opt -key:val"#);
+        assert_eq!(p.parse(command.to_string()).unwrap(), command);
+
+        for name in [r#"say"hi"#, "a-b", "!foo", r"back\slash"] {
+            command.name = name.to_string();
+            assert_eq!(p.parse(command.to_string()).unwrap().name, name);
+        }
+    }
+
+    #[test]
+    fn arg_test() {
+        let mut command = command();
+        command.push_arg("arg2");
+        command.push_arg("arg3");
+
+        assert_eq!(command.arg(1), Some("arg2"));
+        assert_eq!(command.arg(3), None);
+        assert_eq!(command.first_arg(), Some("arg1"));
+        assert_eq!(command.args_from(1), ["arg2", "arg3"]);
+        assert!(command.args_from(3).is_empty());
+        assert!(command.args_from(10).is_empty());
+
+        assert_eq!(command.require_arg(2), Ok("arg3"));
+        assert_eq!(command.require_arg(3), Err(MissingArg(3)));
+        assert_eq!(command.require_arg(3).unwrap_err().to_string(), "missing argument 3");
+
+        command.arguments.clear();
+        assert_eq!(command.first_arg(), None);
+        assert_eq!(command.require_arg(0), Err(MissingArg(0)));
+    }
+
+    #[test]
+    fn bind_positional_test() {
+        let p = crate::Parser::new('!', '-');
+        let names = ["from", "to"];
+
+        let command = p.parse("!move a1 b2").unwrap();
+        assert_eq!(command.bind_positional(&names), HashMap::from([("from", "a1"), ("to", "b2")]));
+
+        let command = p.parse("!move a1").unwrap();
+        assert_eq!(command.bind_positional(&names), HashMap::from([("from", "a1")]));
+
+        let command = p.parse("!move a1 b2 c3 -opt").unwrap();
+        assert_eq!(command.bind_positional(&names), HashMap::from([("from", "a1"), ("to", "b2")]));
+    }
+
+    #[test]
+    fn iter_test() {
+        let mut command = command();
+        command.push_arg("arg2");
+
+        let arguments: Vec<&str> = command.arguments_iter().collect();
+        assert_eq!(arguments, vec!["arg1", "arg2"]);
+
+        let options: Vec<&str> = command.options_iter().collect();
+        assert_eq!(options, vec!["opt"]);
+
+        let parameters: Vec<(&str, &str)> = command.parameters_iter().collect();
+        assert_eq!(parameters, vec![("key", "val")]);
+    }
+
+    #[test]
+    fn expand_templates_test() {
+        let mut command = command();
+        command.push_arg("{0} and {1}");
+        command.set_param("msg", "Hello {0}, {2}{1}");
+        command.set_param("missing", "{3} {x} {} {0");
+        command.set_param("braces", "{{0}} }}{{ {{{0}}}");
+
+        command.expand_templates();
+
+        assert_eq!(command.arguments, vec!["arg1", "arg1 and {0} and {1}"]);
+        assert_eq!(command.parameters.get("msg").unwrap(), "Hello arg1, {2}{0} and {1}");
+        assert_eq!(command.parameters.get("missing").unwrap(), "{3} {x} {} {0");
+        assert_eq!(command.parameters.get("braces").unwrap(), "{0} }{ {arg1}");
+        assert_eq!(command.parameters.get("key").unwrap(), "val");
+    }
+
+    #[test]
+    fn retain_test() {
+        let mut command = command();
+        command.options.insert("verbose".to_string());
+        command.options.insert("debug".to_string());
+        command.set_param("user", "alice");
+        command.set_param("token", "secret");
+
+        command.retain_options(|opt| opt != "debug");
+        command.retain_parameters(|key, val| key != "token" && val != "val");
+
+        assert_eq!(command.options_sorted(), vec!["opt", "verbose"]);
+        assert_eq!(command.parameters_sorted(), vec![("user", "alice")]);
+    }
+
+    #[test]
+    fn args_as_number_test() {
+        let p = crate::Parser::new('!', '-');
+
+        // negative numbers have to be quoted, otherwise they are options
+        let command = p.parse(r#"!calc 3 "-4" 1.5 -opt"#).unwrap();
+        assert_eq!(command.args_as_f64(), Ok(vec![3.0, -4.0, 1.5]));
+        assert!(matches!(command.args_as_i64(), Err((2, _))));
+
+        let command = p.parse("!calc 3 + 4").unwrap();
+        assert!(matches!(command.args_as_f64(), Err((1, _))));
+
+        let command = p.parse(r#"!calc 3 "-4""#).unwrap();
+        assert_eq!(command.args_as_i64(), Ok(vec![3, -4]));
+    }
+
+    #[test]
+    fn repeated_option_level_test() {
+        let mut p = crate::Parser::new('!', '-');
+        p.duplicate_option = DuplicateMode::Count;
+
+        let mut command = p.parse("!foo -v -v -q -v").unwrap();
+        assert_eq!(command.repeated_option_level("v"), 3);
+        assert_eq!(command.repeated_option_level("q"), 1);
+        assert_eq!(command.repeated_option_level("x"), 0);
+
+        command.options.insert("x".to_string());
+        assert_eq!(command.repeated_option_level("x"), 1);
+
+        command.remove_option("v");
+        assert_eq!(command.repeated_option_level("v"), 0);
+    }
+
+    #[test]
+    fn to_string_with_test() {
+        let bang = crate::Parser::new('!', '-');
+        let slash = crate::Parser::new('/', '+');
+
+        let command = bang.parse(r#"!foo "+arg" -opt -"a b" -key:"long val" "-x""#).unwrap();
+        let rendered = command.to_string_with('/', '+');
+        assert_eq!(rendered, r#"/foo "+arg" "-x" +"a b" +opt +key:"long val""#);
+
+        let reparsed = slash.parse(&rendered).unwrap();
+        assert!(reparsed.semantic_eq(&command));
+        assert_ne!(reparsed, command);
+        assert_eq!(command.to_string_with('!', '-'), command.to_string());
+    }
+
+    #[test]
+    fn coerce_test() {
+        let p = crate::Parser::new('!', '-');
+        let schema = Schema::new()
+            .arg::<u32>()
+            .arg::<i64>()
+            .param::<u8>("times")
+            .param::<bool>("loud");
+
+        let typed = p.parse(r#"!add 3 "-4" extra -times:2"#).unwrap().coerce(&schema).unwrap();
+        assert_eq!(typed.name, "add");
+        assert_eq!(typed.arg::<u32>(0), Some(&3));
+        assert_eq!(typed.arg::<i64>(1), Some(&-4));
+        assert_eq!(typed.arg::<u32>(1), None);
+        assert_eq!(typed.arg::<u32>(2), None);
+        assert_eq!(typed.param::<u8>("times"), Some(&2));
+        assert_eq!(typed.param::<bool>("loud"), None);
+
+        let e = p.parse("!add 3 four").unwrap().coerce(&schema).unwrap_err();
+        assert!(matches!(e, CoerceError::InvalidArgument(1, _)));
+        assert_eq!(e.to_string(), "invalid argument 1: invalid digit found in string");
+
+        let e = p.parse("!add 3 4 -times:300").unwrap().coerce(&schema).unwrap_err();
+        assert!(matches!(&e, CoerceError::InvalidParameter(key, _) if key == "times"));
+
+        assert!(matches!(p.parse("!add 3").unwrap().coerce(&schema), Err(CoerceError::MissingArgument(1))));
+    }
+
+    #[test]
+    fn flags_test() {
+        let p = crate::Parser::new('!', '-');
+        let command = p.parse("!foo -b -k:v -a:1 -b:2 -c").unwrap();
+
+        let flags = command.flags();
+        assert_eq!(flags, vec![
+            ("a".to_string(), Some("1".to_string())),
+            ("b".to_string(), None),
+            ("b".to_string(), Some("2".to_string())),
+            ("c".to_string(), None),
+            ("k".to_string(), Some("v".to_string())),
+        ]);
+
+        let rebuilt = Command::from_flags('!', '-', "foo".to_string(), flags);
+        assert_eq!(rebuilt, command);
+        assert_eq!(rebuilt.flags(), command.flags());
+
+        assert!(Command::from_flags('!', '-', "foo".to_string(), Vec::new()).flags().is_empty());
+    }
+
+    #[test]
+    fn approx_heap_bytes_test() {
+        let p = crate::Parser::new('!', '-');
+        let mut command = p.parse("!foo -opt -key:val").unwrap();
+        assert_eq!(command.options_and_params_len(), 2);
+
+        let mut last = command.approx_heap_bytes();
+        assert!(last >= "foo".len() + "opt".len() + "key".len() + "val".len());
+
+        for i in 0..20 {
+            command.push_arg(format!("argument {i}"));
+            let bytes = command.approx_heap_bytes();
+            assert!(bytes > last);
+            last = bytes;
+        }
+
+        // quote styles and key casing are allocated as well
+        let mut p = crate::Parser::new('!', '-');
+        p.case_insensitive_keys = true;
+
+        let plain = p.parse("!foo -key:val").unwrap();
+        let written = p.parse(r#"!foo -KEY:"val""#).unwrap();
+        assert_eq!(plain, written);
+        assert!(written.approx_heap_bytes() > plain.approx_heap_bytes() + "KEY".len());
+    }
+
+    #[test]
+    fn canonical_string_test() {
+        let mut p = crate::Parser::new('!', '-');
+        p.single_quotes = true;
+
+        let a = p.parse(r#"!foo -z -a -k:"v w" -j:1 arg "-x" 'b c'"#).unwrap();
+        let b = p.parse(r#"!foo arg -j:'1' "-x" -k:'v w' -a 'b c' -z -a"#).unwrap();
+        assert!(a.semantic_eq(&b));
+        assert_ne!(a.to_string(), b.to_string());
+        assert_eq!(a.canonical_string(), b.canonical_string());
+        assert_eq!(a.canonical_string(), r#"!foo -a -z -j:1 -k:"v w" arg "-x" "b c""#);
+
+        let mut q = crate::Parser::new('/', '+');
+        q.single_quotes = true;
+        let mut c = q.parse(a.to_string_with('/', '+')).unwrap();
+        assert_eq!(c.canonical_string(), a.canonical_string());
+
+        c.push_arg("d");
+        assert_ne!(c.canonical_string(), a.canonical_string());
+    }
+
+    #[test]
+    fn parts_test() {
+        let command = command();
+        let parts = command.parts();
+
+        assert_eq!(parts.name, command.name);
+        assert_eq!(parts.arguments, command.arguments.as_slice());
+        assert_eq!(parts.options, &command.options);
+        assert_eq!(parts.parameters, &command.parameters);
+
+        let other = crate::Parser::new('/', '+').parse(command.to_string_with('/', '+')).unwrap();
+        assert_eq!(other.parts(), parts);
+    }
+
+    #[test]
+    fn with_prefix_test() {
+        let p = crate::Parser::new('!', '-');
+        let command = p.parse("!foo -verbose -version -quiet -verb:1 -level:2").unwrap();
+
+        let mut options: Vec<&str> = command.options_with_prefix("ver").collect();
+        options.sort_unstable();
+        assert_eq!(options, vec!["verbose", "version"]);
+
+        let parameters: Vec<(&str, &str)> = command.parameters_with_prefix("ver").collect();
+        assert_eq!(parameters, vec![("verb", "1")]);
+
+        assert_eq!(command.options_with_prefix("x").count(), 0);
+        assert_eq!(command.parameters_with_prefix("x").count(), 0);
+
+        assert_eq!(command.options_with_prefix("").count(), 3);
+        assert_eq!(command.parameters_with_prefix("").count(), 2);
+
+        // the items outlive a prefix that is dropped right away
+        let typed = String::from("qu");
+        let options: Vec<&str> = {
+            let prefix = typed.clone();
+            command.options_with_prefix(&prefix).collect()
+        };
+        let parameters: Vec<(&str, &str)> = {
+            let prefix = typed.clone();
+            command.parameters_with_prefix(&prefix).collect()
+        };
+        assert_eq!(options, vec!["quiet"]);
+        assert!(parameters.is_empty());
+    }
+
+    #[test]
+    fn apply_defaults_test() {
+        let p = crate::Parser::new('!', '-');
+        let mut command = p.parse("!serve -port:9000 -host:").unwrap();
+
+        command.apply_defaults(&HashMap::from([
+            ("port".to_string(), "8080".to_string()),
+            ("host".to_string(), "localhost".to_string()),
+            ("mode".to_string(), "dev".to_string()),
+        ]));
+        assert_eq!(command.parameters_sorted(), vec![("host", ""), ("mode", "dev"), ("port", "9000")]);
+
+        command.apply_defaults(&HashMap::new());
+        assert_eq!(command.parameters.len(), 3);
+    }
+
+    #[test]
+    fn display_plain_test() {
+        let p = crate::Parser::new('!', '-');
+        let command = p.parse(r#"!"my cmd" -b "-x y" -a -"k 1":"v 2" -j:w z"#).unwrap();
+
+        let plain = command.display_plain();
+        assert_eq!(plain, r#""my cmd" "-x y" z a b j:w "k 1":"v 2""#);
+        assert!(!plain.contains('!'));
+        assert_eq!(command.to_string(), r#"!"my cmd" "-x y" z -a -b -j:w -"k 1":"v 2""#);
+    }
+
+    #[test]
+    fn rename_param_test() {
+        let p = crate::Parser::new('!', '-');
+
+        let mut command = p.parse(r#"!run -o:"out.txt" -f:old"#).unwrap();
+        assert!(command.rename_param("o", "output"));
+        assert_eq!(command.parameters.get("output"), Some(&"out.txt".to_string()));
+        assert!(!command.parameters.contains_key("o"));
+        assert_eq!(command.quote_styles.parameter("output"), QuoteStyle::Double);
+        assert_eq!(command.to_string(), r#"!run -f:old -output:"out.txt""#);
+
+        assert!(!command.rename_param("x", "y"));
+        assert!(!command.parameters.contains_key("y"));
+
+        // the renamed value replaces the one already there
+        assert!(command.rename_param("output", "f"));
+        assert_eq!(command.parameters, HashMap::from([("f".to_string(), "out.txt".to_string())]));
+    }
+
+    #[test]
+    fn alias_option_test() {
+        let mut p = crate::Parser::new('!', '-');
+        p.duplicate_option = DuplicateMode::Count;
+
+        let mut command = p.parse("!run -q -q -v").unwrap();
+        assert!(command.alias_option("q", "quiet"));
+        assert_eq!(command.options, HashSet::from(["quiet".to_string(), "v".to_string()]));
+        assert_eq!(command.repeated_option_level("quiet"), 2);
+        assert_eq!(command.repeated_option_level("q"), 0);
+
+        assert!(!command.alias_option("x", "y"));
+        assert!(!command.options.contains("y"));
+
+        assert!(command.alias_option("v", "quiet"));
+        assert_eq!(command.options, HashSet::from(["quiet".to_string()]));
+        assert_eq!(command.repeated_option_level("quiet"), 2);
+    }
+
+    #[test]
+    fn pretty_test() {
+        let mut command = command();
+        command.push_arg("long arg");
+
+        let pretty = command.pretty();
+        assert!(pretty.contains("name: foo\n"));
+        assert!(pretty.contains("  0: arg1\n"));
+        assert!(pretty.contains("  1: long arg\n"));
+        assert!(pretty.contains("options:\n  opt\n"));
+        assert!(pretty.contains("parameters:\n  key: val\n"));
+    }
+
+    #[test]
+    fn is_test() {
+        let mut command = command();
+
+        assert!(command.is("foo"));
+        assert!(!command.is("bar"));
+        assert!(command.is_any(&["bar", "foo"]));
+        assert!(!command.is_any(&["bar", "baz"]));
+        assert!(!command.is_any(&[]));
+
+        command.name = "Foo".to_string();
+
+        assert!(!command.is("foo"));
+        assert!(command.is_ignore_case("foo"));
+        assert!(command.is_ignore_case("FOO"));
+        assert!(command.is_any_ignore_case(&["bar", "fOo"]));
+        assert!(!command.is_any_ignore_case(&["bar", "baz"]));
+    }
+
+    #[test]
+    fn param_list_test() {
+        let mut command = command();
+        command.set_param("tags", "a,b,c");
+        command.set_param("gaps", "a,,b,");
+        command.set_param("empty", "");
+
+        assert_eq!(command.param_list("tags", ',', true), Some(vec!["a", "b", "c"]));
+        assert_eq!(command.param_list("gaps", ',', true), Some(vec!["a", "b"]));
+        assert_eq!(command.param_list("empty", ',', true), Some(vec![]));
+        assert_eq!(command.param_list("key", ',', true), Some(vec!["val"]));
+        assert_eq!(command.param_list("missing", ',', true), None);
+
+        assert_eq!(command.param_list("tags", ',', false), Some(vec!["a", "b", "c"]));
+        assert_eq!(command.param_list("gaps", ',', false), Some(vec!["a", "", "b", ""]));
+        assert_eq!(command.param_list("empty", ',', false), Some(vec![""]));
+        assert_eq!(command.param_list("missing", ',', false), None);
+        assert_eq!(command.parameters.get("gaps"), Some(&"a,,b,".to_string()));
+    }
+}
@@ -0,0 +1,160 @@
+use crate::command::Command;
+
+/// A change to the arguments of a command, returned as part of a [`CommandDiff`].
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum ArgumentChange {
+    /// The argument at this index of the old command is missing in the new one.
+    Removed(usize, String),
+    /// The argument at this index of the new command is missing in the old one.
+    Added(usize, String),
+}
+
+/// Differences between two commands, created by [`Command::diff`](crate::Command::diff).
+///
+/// Options and parameters are sorted by name. Prefixes are not compared.
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct CommandDiff {
+    /// Old and new name, if the name changed.
+    pub name: Option<(String, String)>,
+    /// Smallest set of removals and insertions turning the old arguments into the new ones.
+    ///
+    /// Removals are listed before insertions.
+    pub arguments: Vec<ArgumentChange>,
+    pub added_options: Vec<String>,
+    pub removed_options: Vec<String>,
+    /// Parameters only in the new command, as key and value.
+    pub added_parameters: Vec<(String, String)>,
+    /// Parameters only in the old command, as key and value.
+    pub removed_parameters: Vec<(String, String)>,
+    /// Parameters in both commands with different values, as key, old value and new value.
+    pub changed_parameters: Vec<(String, String, String)>,
+}
+
+impl CommandDiff {
+    pub(crate) fn new(old: &Command, new: &Command) -> CommandDiff {
+        let mut diff = CommandDiff {
+            arguments: diff_arguments(&old.arguments, &new.arguments),
+            ..Default::default()
+        };
+
+        if old.name != new.name {
+            diff.name = Some((old.name.clone(), new.name.clone()));
+        }
+
+        diff.added_options = new.options_sorted().into_iter()
+            .filter(|opt| !old.options.contains(*opt))
+            .map(String::from)
+            .collect();
+        diff.removed_options = old.options_sorted().into_iter()
+            .filter(|opt| !new.options.contains(*opt))
+            .map(String::from)
+            .collect();
+
+        for (key, val) in new.parameters_sorted() {
+            match old.parameters.get(key) {
+                None => diff.added_parameters.push((key.to_string(), val.to_string())),
+                Some(old_val) if old_val != val => {
+                    diff.changed_parameters.push((key.to_string(), old_val.clone(), val.to_string()));
+                }
+                Some(_) => {}
+            }
+        }
+
+        diff.removed_parameters = old.parameters_sorted().into_iter()
+            .filter(|(key, _)| !new.parameters.contains_key(*key))
+            .map(|(key, val)| (key.to_string(), val.to_string()))
+            .collect();
+
+        diff
+    }
+
+    /// Whether both commands are the same, ignoring prefixes.
+    pub fn is_empty(&self) -> bool {
+        *self == CommandDiff::default()
+    }
+}
+
+/// Diffs two argument lists using their longest common subsequence.
+fn diff_arguments(old: &[String], new: &[String]) -> Vec<ArgumentChange> {
+    // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut removed = vec![];
+    let mut added = vec![];
+    let (mut i, mut j) = (0, 0);
+
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            removed.push(ArgumentChange::Removed(i, old[i].clone()));
+            i += 1;
+        } else {
+            added.push(ArgumentChange::Added(j, new[j].clone()));
+            j += 1;
+        }
+    }
+
+    removed.extend(added);
+    removed
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn diff(old: &str, new: &str) -> CommandDiff {
+        let p = Parser::new('!', '-');
+        p.parse(old).unwrap().diff(&p.parse(new).unwrap())
+    }
+
+    #[test]
+    fn option_test() {
+        let diff = diff("!foo -a -b", "!foo -b -c -d");
+
+        assert_eq!(diff.added_options, vec!["c", "d"]);
+        assert_eq!(diff.removed_options, vec!["a"]);
+        assert!(diff.added_parameters.is_empty());
+    }
+
+    #[test]
+    fn parameter_test() {
+        let diff = diff("!foo -a:1 -b:2 -c:3", "!foo -a:1 -c:4 -d:5");
+
+        assert_eq!(diff.removed_parameters, vec![("b".to_string(), "2".to_string())]);
+        assert_eq!(diff.changed_parameters, vec![("c".to_string(), "3".to_string(), "4".to_string())]);
+        assert_eq!(diff.added_parameters, vec![("d".to_string(), "5".to_string())]);
+        assert!(diff.added_options.is_empty());
+    }
+
+    #[test]
+    fn argument_test() {
+        let diff = diff("!foo a b c", "!bar a x c d");
+
+        assert_eq!(diff.name, Some(("foo".to_string(), "bar".to_string())));
+        assert_eq!(diff.arguments, vec![
+            ArgumentChange::Removed(1, "b".to_string()),
+            ArgumentChange::Added(1, "x".to_string()),
+            ArgumentChange::Added(3, "d".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn empty_test() {
+        assert!(diff("!foo a -b -c:d", "!foo a -c:d -b").is_empty());
+        assert!(!diff("!foo a", "!foo").is_empty());
+    }
+}
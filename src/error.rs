@@ -1,11 +1,179 @@
+use std::fmt;
 use thiserror::Error as ThisError;
 
+/// Position of an error in the parsed string.
+///
+/// Both the index of the character and its byte offset are stored,
+/// so the input can be sliced at the error without walking it again.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Span {
+    pub char_start: usize,
+    pub byte_start: usize,
+}
+
+impl Span {
+    pub fn new(char_start: usize, byte_start: usize) -> Self {
+        Span { char_start, byte_start }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.char_start)
+    }
+}
+
+/// Returned by a [`Parser`](crate::Parser) for input that isn't a valid command.
+///
+/// New variants may be added, so use [`kind`](ParseError::kind) and [`position`](ParseError::position)
+/// to handle errors without matching every variant.
 #[derive(Debug, ThisError)]
+#[non_exhaustive]
 pub enum ParseError {
     #[error("failed to parse prefix at position {0} (found {1})")]
-    PrefixError(usize, char),
+    PrefixError(Span, char),
     #[error("failed to parse command name at position {0} (found {1})")]
-    NameError(usize, char),
+    NameError(Span, char),
     #[error("failed to escape character at position {0} (found {1})")]
-    EscapeError(usize, char),
-}
\ No newline at end of file
+    EscapeError(Span, char),
+    #[error("unterminated quote starting at position {0}")]
+    UnterminatedQuote(Span),
+    #[error("unexpected character at position {0} (found {1})")]
+    UnexpectedCharacter(Span, char),
+    #[error("parameters are not allowed, found one at position {0}")]
+    ParametersNotAllowed(Span),
+    #[error("token too long at position {0}")]
+    TokenTooLong(Span),
+    #[error("input ends with an unfinished escape sequence at position {0}")]
+    DanglingEscape(Span),
+    #[error("input is empty")]
+    EmptyInput,
+    #[error("unexpected argument at position {0}")]
+    UnexpectedArgument(Span),
+    #[error("duplicate parameter {1} at position {0}")]
+    DuplicateParameter(Span, String),
+    #[error("unexpected command prefix at position {0}")]
+    UnexpectedPrefix(Span),
+    #[error("unexpected input after the command at position {0}")]
+    TrailingInput(Span),
+    #[error("duplicate option {1} at position {0}")]
+    DuplicateOption(Span, String),
+}
+
+impl ParseError {
+    /// Position in the input the error refers to, if there is one.
+    pub fn position(&self) -> Option<Span> {
+        match self {
+            ParseError::PrefixError(span, _) |
+            ParseError::NameError(span, _) |
+            ParseError::EscapeError(span, _) |
+            ParseError::UnterminatedQuote(span) |
+            ParseError::UnexpectedCharacter(span, _) |
+            ParseError::ParametersNotAllowed(span) |
+            ParseError::TokenTooLong(span) |
+            ParseError::DanglingEscape(span) |
+            ParseError::UnexpectedArgument(span) |
+            ParseError::DuplicateParameter(span, _) |
+            ParseError::UnexpectedPrefix(span) |
+            ParseError::TrailingInput(span) |
+            ParseError::DuplicateOption(span, _) => Some(*span),
+            ParseError::EmptyInput => None,
+        }
+    }
+
+    /// What kind of mistake in the input caused the error.
+    pub fn kind(&self) -> ParseErrorKind {
+        match self {
+            ParseError::PrefixError(..) | ParseError::UnexpectedPrefix(_) => ParseErrorKind::Prefix,
+            ParseError::NameError(..) => ParseErrorKind::Name,
+            ParseError::EscapeError(..) | ParseError::DanglingEscape(_) => ParseErrorKind::Escape,
+            ParseError::UnterminatedQuote(_) => ParseErrorKind::Quote,
+            ParseError::UnexpectedCharacter(..) | ParseError::UnexpectedArgument(_) |
+            ParseError::TrailingInput(_) => ParseErrorKind::Unexpected,
+            ParseError::ParametersNotAllowed(_) | ParseError::DuplicateParameter(..) |
+            ParseError::DuplicateOption(..) => ParseErrorKind::NotAllowed,
+            ParseError::TokenTooLong(_) => ParseErrorKind::TooLong,
+            ParseError::EmptyInput => ParseErrorKind::Empty,
+        }
+    }
+}
+
+/// Broad classification of a [`ParseError`], returned by [`ParseError::kind`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum ParseErrorKind {
+    /// The prefix is missing or in the wrong place.
+    Prefix,
+    /// The name is empty or contains a character it can't contain.
+    Name,
+    /// An escape sequence is unknown or unfinished.
+    Escape,
+    /// A quote is never closed.
+    Quote,
+    /// A character or token appears where it isn't expected.
+    Unexpected,
+    /// Valid syntax that the parser is configured to reject.
+    NotAllowed,
+    /// A token exceeds [`Parser::max_token_len`](crate::Parser::max_token_len).
+    TooLong,
+    /// There is no input at all.
+    Empty,
+}
+
+/// Returned by [`Parser::try_new`](crate::Parser::try_new), [`Parser::validate`](crate::Parser::validate)
+/// and [`Command::check_prefixes`](crate::Command::check_prefixes)
+/// for a configuration that can't be parsed unambiguously.
+#[derive(Debug, Eq, PartialEq, ThisError)]
+pub enum ConfigError {
+    #[error("option prefix {0} is also a quote")]
+    OptionPrefixIsQuote(char),
+    #[error("option prefix {0:?} is whitespace")]
+    OptionPrefixIsWhitespace(char),
+    #[error("prefix and option prefix are both {0}")]
+    PrefixesEqual(char),
+    #[error("{0:?} can't be used as a prefix")]
+    ReservedPrefix(char),
+}
+
+/// Returned by [`Command::require_arg`](crate::Command::require_arg) for an argument that doesn't exist.
+#[derive(Debug, Eq, PartialEq, ThisError)]
+#[error("missing argument {0}")]
+pub struct MissingArg(pub usize);
+
+/// Returned by [`Command::validate_against`](crate::Command::validate_against)
+/// when a command doesn't match a [`CommandSpec`](crate::CommandSpec).
+#[derive(Debug, Eq, PartialEq, ThisError)]
+pub enum ValidationError {
+    #[error("missing required parameter {0}")]
+    MissingParameter(String),
+    #[error("unknown option {0}")]
+    UnknownOption(String),
+    #[error("too few arguments (expected at least {0}, found {1})")]
+    TooFewArguments(usize, usize),
+    #[error("too many arguments (expected at most {0}, found {1})")]
+    TooManyArguments(usize, usize),
+}
+
+/// The error of a [`FromStr`](std::str::FromStr) implementation that failed during coercion.
+pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Returned by [`Command::coerce`](crate::Command::coerce)
+/// when a command's values don't have the types declared in a [`Schema`](crate::Schema).
+#[derive(Debug, ThisError)]
+pub enum CoerceError {
+    #[error("missing argument {0}")]
+    MissingArgument(usize),
+    #[error("invalid argument {0}: {1}")]
+    InvalidArgument(usize, #[source] BoxedError),
+    #[error("invalid value for parameter {0}: {1}")]
+    InvalidParameter(String, #[source] BoxedError),
+}
+
+/// Returned by [`Parser::parse_lines`](crate::Parser::parse_lines) for a line that couldn't be read or parsed.
+#[derive(Debug, ThisError)]
+pub enum ParseLinesError {
+    #[error("failed to read line: {0}")]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+}
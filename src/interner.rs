@@ -0,0 +1,80 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// Deduplicates strings that are seen over and over, like command names.
+///
+/// Used by [`Parser::parse_interned`](crate::Parser::parse_interned).
+#[derive(Debug, Default, Clone)]
+pub struct StringInterner {
+    strings: HashSet<Arc<str>>,
+}
+
+impl StringInterner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the shared copy of `s`, storing one first if `s` hasn't been seen before.
+    pub fn intern(&mut self, s: &str) -> Arc<str> {
+        if let Some(interned) = self.strings.get(s) {
+            return Arc::clone(interned);
+        }
+
+        let interned: Arc<str> = Arc::from(s);
+        self.strings.insert(Arc::clone(&interned));
+        interned
+    }
+
+    /// Number of distinct strings stored.
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+/// A [`Command`](crate::Command) whose name, options and parameter keys are shared through a [`StringInterner`].
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct InternedCommand {
+    pub prefix: char,
+    pub option_prefix: char,
+    pub name: Arc<str>,
+    pub arguments: Vec<String>,
+    pub options: HashSet<Arc<str>>,
+    pub parameters: HashMap<Arc<str>, String>,
+}
+
+/// A [`Command`](crate::Command) whose options and parameter keys are shared, but nothing else.
+///
+/// Created by [`Parser::parse_with_key_interner`](crate::Parser::parse_with_key_interner).
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct KeyInternedCommand {
+    pub prefix: char,
+    pub option_prefix: char,
+    pub name: String,
+    pub arguments: Vec<String>,
+    pub options: HashSet<Arc<str>>,
+    pub parameters: HashMap<Arc<str>, String>,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intern_test() {
+        let mut interner = StringInterner::new();
+        assert!(interner.is_empty());
+
+        let a = interner.intern("foo");
+        let b = interner.intern("foo");
+        let c = interner.intern("bar");
+
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(!Arc::ptr_eq(&a, &c));
+        assert_eq!(interner.len(), 2);
+    }
+}
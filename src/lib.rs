@@ -8,17 +8,22 @@
 //!
 //! A command consists of 4 different parts:
 //! - _name_: The name of the command is the first word after the prefix.
-//! In the example above that's `foo`.
+//!   It can be enclosed by `"` to include spaces, like `!"two words"`.
+//!   A `\` starts an escape sequence in a quoted name, in an unquoted name only with [`Parser::escape_in_name`].
+//!   In the example above that's `foo`.
 //! - _arguments_: Arguments are simple strings passed to the command.
-//! They are either single words or strings with spaces enclosed by `"`.
-//! In the example the two arguments are `arg1` and `long arg 2`.
+//!   They are either single words or strings with spaces enclosed by `"`.
+//!   In the example the two arguments are `arg1` and `long arg 2`.
 //! - _options_: Options are a set of words.
-//! They are prefixed with the `option_prefix`.
-//! The only option in the example is `opt`.
+//!   They are prefixed with the `option_prefix`.
+//!   The only option in the example is `opt`.
 //! - _parameters_: Parameters are key-value pairs.
-//! They are prefixed with the `option_prefix` and seperated by `:`.
-//! The value part of the pair can be a word or a string enclosed by `"`.
-//! In the example above `key1`s value is `val1` and `key2`s value is `long val2`.
+//!   They are prefixed with the `option_prefix` and seperated by `:`.
+//!   The value part of the pair can be a word or a string enclosed by `"`.
+//!   The key can be enclosed by `"` as well to include spaces, like `-"full name":Alice`.
+//!   A quoted key that isn't followed by `:` is an option.
+//!   A key followed by `:` and nothing else, like `-key:`, is a parameter with an empty value.
+//!   In the example above `key1`s value is `val1` and `key2`s value is `long val2`.
 //!
 //! # Escaping
 //!
@@ -29,6 +34,11 @@
 //! - `\"`: produces `"`
 //! - `\\`: produces `\`
 //!
+//! A message that starts with `\` directly followed by the prefix (like `\!foo`) is not a command.
+//! [`Parser::try_parse`](crate::Parser::try_parse) returns `Ok(None)` for it and
+//! [`Parser::strip_escape`](crate::Parser::strip_escape) removes the `\` so the message can be shown as `!foo`.
+//! This is the only place outside of quotes where `\` escapes anything.
+//!
 //! # Example
 //!
 //! ```
@@ -45,10 +55,33 @@
 //! assert_eq!(command.parameters.get("key2"), Some(&"long val2".to_string()));
 //! ```
 
+mod builder;
+#[cfg(feature = "clap")]
+mod clap_adapter;
 mod command;
+mod diff;
 mod error;
+mod interner;
 mod parser;
+mod progress;
+mod schema;
+mod spec;
+#[cfg(test)]
+mod testing;
+mod tokenizer;
+mod visitor;
+mod warning;
 
 pub use parser::*;
+pub use builder::*;
+#[cfg(feature = "clap")]
+pub use clap_adapter::*;
 pub use command::*;
+pub use diff::*;
 pub use error::*;
+pub use interner::*;
+pub use progress::*;
+pub use schema::*;
+pub use spec::*;
+pub use visitor::*;
+pub use warning::*;
@@ -1,21 +1,52 @@
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
-use crate::command::Command;
-use crate::error::ParseError;
-use crate::error::ParseError::{EscapeError, NameError, PrefixError};
-
-#[derive(Debug, Copy, Clone)]
-enum ParseState {
-    Prefix,
-    Name,
-    Default,
-    Argument,
-    LongArgument,
-    EscapeLongArg,
-    Option,
-    ParamConnector,
-    ParamVal,
-    ParamLongVal,
-    EscapeLongParamVal,
+use std::io::BufRead;
+use std::iter;
+use std::sync::Arc;
+use crate::command::{Command, CommandCow, ShallowCommand, TokenSpans};
+use crate::error::{ConfigError, ParseError, ParseLinesError, Span};
+use crate::error::ParseError::PrefixError;
+use crate::interner::{InternedCommand, KeyInternedCommand, StringInterner};
+use crate::progress::ParseProgress;
+use crate::tokenizer::{Token, Tokenizer};
+use crate::visitor::ParseVisitor;
+use crate::warning::Warning;
+
+fn is_space(c: char) -> bool {
+    c == ' '
+}
+
+/// Writes `c` as an EBNF terminal.
+fn terminal(c: char) -> String {
+    if c == '"' {
+        "'\"'".to_string()
+    } else {
+        format!("\"{}\"", c)
+    }
+}
+
+/// What a [`Parser`] does with an escape sequence it doesn't know, like `\x`.
+#[derive(Debug, Default, Eq, PartialEq, Copy, Clone)]
+pub enum UnknownEscape {
+    /// Fail with [`ParseError::EscapeError`].
+    #[default]
+    Error,
+    /// Keep both the `\` and the following character.
+    Keep,
+    /// Drop the `\` and keep the following character.
+    Strip,
+}
+
+/// What a [`Parser`] does with an option that is given more than once.
+#[derive(Debug, Default, Eq, PartialEq, Copy, Clone)]
+pub enum DuplicateMode {
+    /// Keep the option once, so [`Command::repeated_option_level`] is at most `1`.
+    #[default]
+    KeepOne,
+    /// Fail with [`ParseError::DuplicateOption`] at the start of the second option.
+    Error,
+    /// Keep the option once and count how often it was given in [`Command::repeated_option_level`].
+    Count,
 }
 
 /// Used to parse a [`Command`] from a string.
@@ -31,17 +62,22 @@ enum ParseState {
 ///
 /// A command consists of 4 different parts:
 /// - _name_: The name of the command is the first word after the prefix.
-/// In the example above that's `foo`.
+///   It can be enclosed by `"` to include spaces, like `!"two words"`.
+///   A `\` starts an escape sequence in a quoted name, in an unquoted name only with [`Parser::escape_in_name`].
+///   In the example above that's `foo`.
 /// - _arguments_: Arguments are simple strings passed to the command.
-/// They are either single words or strings with spaces enclosed by `"`.
-/// In the example the two arguments are `arg1` and `long arg 2`.
+///   They are either single words or strings with spaces enclosed by `"`.
+///   In the example the two arguments are `arg1` and `long arg 2`.
 /// - _options_: Options are a set of words.
-/// They are prefixed with the `option_prefix`.
-/// The only option in the example is `opt`.
+///   They are prefixed with the `option_prefix`.
+///   The only option in the example is `opt`.
 /// - _parameters_: Parameters are key-value pairs.
-/// They are prefixed with the `option_prefix` and seperated by `:`.
-/// The value part of the pair can be a word or a string enclosed by `"`.
-/// In the example above `key1`s value is `val1` and `key2`s value is `long val2`.
+///   They are prefixed with the `option_prefix` and seperated by `:`.
+///   The value part of the pair can be a word or a string enclosed by `"`.
+///   The key can be enclosed by `"` as well to include spaces, like `-"full name":Alice`.
+///   A quoted key that isn't followed by `:` is an option.
+///   A key followed by `:` and nothing else, like `-key:`, is a parameter with an empty value.
+///   In the example above `key1`s value is `val1` and `key2`s value is `long val2`.
 ///
 /// # Escaping
 ///
@@ -52,6 +88,11 @@ enum ParseState {
 /// - `\"`: produces `"`
 /// - `\\`: produces `\`
 ///
+/// A message that starts with `\` directly followed by the prefix (like `\!foo`) is not a command.
+/// [`Parser::try_parse`](crate::Parser::try_parse) returns `Ok(None)` for it and
+/// [`Parser::strip_escape`](crate::Parser::strip_escape) removes the `\` so the message can be shown as `!foo`.
+/// This is the only place outside of quotes where `\` escapes anything.
+///
 /// # Example
 ///
 /// ```
@@ -61,17 +102,17 @@ enum ParseState {
 /// let p = Parser::new('!', '-');
 /// let command_string = r##"!foo arg1 "long arg 2" -opt -opt -key1:val1 -key2:"long val2""##;
 ///
-/// let command = Command {
-///     prefix: '!',
-///     option_prefix: '-',
-///     name: "foo".to_string(),
-///     arguments: vec!["arg1".to_string(), "long arg 2".to_string()],
-///     options: HashSet::from(["opt".to_string()]),
-///     parameters: HashMap::from([
+/// let command = Command::new(
+///     '!',
+///     '-',
+///     "foo".to_string(),
+///     vec!["arg1".to_string(), "long arg 2".to_string()],
+///     HashSet::from(["opt".to_string()]),
+///     HashMap::from([
 ///         ("key1".to_string(), "val1".to_string()),
 ///         ("key2".to_string(), "long val2".to_string())
 ///     ])
-/// };
+/// );
 ///
 /// assert_eq!(p.parse(command_string).unwrap(), command);
 /// ```
@@ -82,240 +123,2569 @@ pub struct Parser {
     /// `<prefix><name> ...`
     ///
     /// Should not be set to `' '` as most chats trim leading spaces.
+    ///
+    /// The prefix is only special as the very first character.
+    /// Anywhere else it's an ordinary character, so `!echo ! -!:!` has the argument `!` and the parameter `!` with value `!`.
+    /// See [`collapse_repeated_prefix`](Parser::collapse_repeated_prefix) for prefixes in front of the name.
+    ///
+    /// Any single `char` works, including emoji like `'🤖'`. Prefixes that consist of multiple `char`s,
+    /// like emoji with a skin tone modifier, are not supported.
+    /// Positions in errors are char indices, so a prefix always counts as one character.
     pub prefix: char,
     /// Prefix of options and parameters.
     ///
     /// `... <option_prefix><option> ... <option_prefix><param key>:<param value>`
     ///
-    /// Should not be set to `' '` or `'"'` as it may not result in expected outcomes.
+    /// Should not be a quote or whitespace, [`Parser::try_new`] and [`Parser::validate`] reject that.
+    /// If it is a quote anyway, the quote wins and `"` always starts a quoted argument.
     pub option_prefix: char,
+    /// Decides which characters separate the parts of a command.
+    ///
+    /// Use [`char::is_whitespace`] to also split on tabs, newlines and other Unicode whitespace,
+    /// or a custom function like `|c| c == ' ' || c == '\u{a0}'`.
+    ///
+    /// Defaults to only accepting `' '`.
+    pub is_whitespace: fn(char) -> bool,
+    /// Skips repeated prefixes in front of the name.
+    ///
+    /// When set, `!!foo` parses like `!foo`. When not set, the name of `!!foo` is `!foo`.
+    ///
+    /// Defaults to `false`.
+    pub collapse_repeated_prefix: bool,
+    /// Skips repeated option prefixes in front of option and parameter keys.
+    ///
+    /// When set, `--help` parses like `-help`. When not set, `--help` is the option `-help`.
+    /// Option prefixes after the first other character are kept, so `-a-b` is always the option `a-b`.
+    ///
+    /// Defaults to `false`.
+    pub collapse_option_prefix: bool,
+    /// Skips spaces between the prefix and the name.
+    ///
+    /// When set, `!  foo` parses like `!foo`. When not set, it fails with [`ParseError::NameError`].
+    ///
+    /// Defaults to `false`.
+    pub trim_name: bool,
+    /// Characters that separate the key of a parameter from its value.
+    ///
+    /// `... <option_prefix><param key><separator><param value>`
+    ///
+    /// An unquoted key ends at its first separator, so a key containing one has to be quoted,
+    /// like `-"a:b":c`. Any separator after that is part of the value, so `-time:12:30` has the
+    /// value `12:30` and with both `':'` and `'='` `-url:a=b` has the value `a=b`.
+    ///
+    /// Any other character is part of the key. With only `'='`, `:` can be used for key paths,
+    /// so `-a:b=1` is the parameter `a:b` with the value `1` and `-a:b` is the option `a:b`.
+    ///
+    /// Defaults to `[':']`.
+    pub param_separators: Cow<'static, [char]>,
+    /// Starts a comment that lasts until the end of the input.
+    ///
+    /// The comment character and everything after it is ignored, unless it is inside quotes.
+    /// It has no effect as the very first character, where only the prefix is expected.
+    ///
+    /// Defaults to `None`.
+    pub comment: Option<char>,
+    /// Ends the command, unless it is inside quotes.
+    ///
+    /// Everything after it is ignored by [`parse`](Parser::parse).
+    /// Use [`parse_one`](Parser::parse_one) to find out where the next command starts.
+    ///
+    /// Defaults to `None`.
+    pub terminator: Option<char>,
+    /// Treats the first character of an option as its name and the rest as its value, like `-n5`.
+    ///
+    /// When set, `-n5` is the parameter `n` with the value `5` and `-abc` is the parameter `a` with the value `bc`.
+    /// Options with a single character, quoted options, tokens containing a separator
+    /// and options listed in [`long_options`](Parser::long_options) are not split.
+    ///
+    /// Defaults to `false`.
+    pub short_option_values: bool,
+    /// Options that are never split by [`short_option_values`](Parser::short_option_values).
+    ///
+    /// Defaults to being empty.
+    pub long_options: Vec<String>,
+    /// Option after which the rest of the input is taken verbatim as one last argument.
+    ///
+    /// When set to `raw`, `!say -raw hello "world" -x` has the option `raw` and the only argument `hello "world" -x`.
+    /// Quotes, escapes, options, comments and terminators in the rest are not interpreted.
+    /// Only the separator directly after the option is removed.
+    ///
+    /// Defaults to `None`.
+    pub raw_after_option: Option<String>,
+    /// Options that take the following argument as their value.
+    ///
+    /// When this contains `o`, `-o file.txt` is the parameter `o` with the value `file.txt`, just like `-o:file.txt`.
+    /// If the option is followed by another option, a parameter or the end of input, it stays an option.
+    ///
+    /// Defaults to being empty.
+    pub value_options: Vec<String>,
+    /// Allows `'` to enclose arguments, keys and values in addition to `"`.
+    ///
+    /// Inside a token enclosed by one kind of quote, the other kind is an ordinary character.
+    /// `\'` produces `'` in any quoted token.
+    ///
+    /// Defaults to `false`.
+    pub single_quotes: bool,
+    /// Joins adjacent quoted and unquoted fragments of an argument or parameter value into one, like a shell does.
+    ///
+    /// When set, a token only ends at a space outside of quotes, so `abc"def"ghi` is the argument `abcdefghi`
+    /// and `-msg:hello" world"` has the value `hello world`.
+    /// When not set, a quote in the middle of an unquoted token is an ordinary character.
+    ///
+    /// Defaults to `false`.
+    pub concat_fragments: bool,
+    /// How escape sequences other than `\"`, `\\` (and `\'` with [`single_quotes`](Parser::single_quotes)) are handled.
+    ///
+    /// Defaults to [`UnknownEscape::Error`].
+    pub unknown_escape: UnknownEscape,
+    /// Rejects stray characters directly after a closing quote.
+    ///
+    /// When set, a quoted argument, option or parameter value has to be followed by a space,
+    /// the option prefix or the end of input,
+    /// otherwise parsing fails with [`ParseError::UnexpectedCharacter`].
+    /// When not set, `"arg"x` parses as the two arguments `arg` and `x`.
+    ///
+    /// Defaults to `false`.
+    pub strict: bool,
+    /// Rejects parameters, for commands that only take options.
+    ///
+    /// When set, a parameter separator after an option fails parsing with [`ParseError::ParametersNotAllowed`]
+    /// at the position of the separator. So does an option that [`short_option_values`](Parser::short_option_values)
+    /// would split, like `-n5`, at the position of its value.
+    ///
+    /// Defaults to `false`.
+    pub flags_only: bool,
+    /// Maximum length of a single token in bytes.
+    ///
+    /// Parsing fails with [`ParseError::TokenTooLong`] at the first character that exceeds it,
+    /// so a huge token is never buffered completely.
+    ///
+    /// Defaults to `None`.
+    pub max_token_len: Option<usize>,
+    /// Drops options that are empty, like the lone `-` in `!foo - bar`.
+    ///
+    /// When not set, such an option is the empty string.
+    ///
+    /// Defaults to `false`.
+    pub ignore_empty_options: bool,
+    /// Lets brackets in quoted arguments and values protect quotes, for JSON-like values.
+    ///
+    /// When set, a quote inside `{}` or `[]` doesn't end the quoted text, so `-data:"{"a":[1]}"`
+    /// has the value `{"a":[1]}`. Brackets are only counted, not matched,
+    /// and the content isn't validated in any way.
+    /// A quoted text with unbalanced brackets runs until the end of input and fails with [`ParseError::UnterminatedQuote`].
+    ///
+    /// Defaults to `false`.
+    pub balanced_brackets: bool,
+    /// Accepts parameters without the option prefix, written like `KEY=VALUE`.
+    ///
+    /// When set, an unquoted argument is split at its first `=` into a parameter,
+    /// so `!run PORT=8080` has the parameter `PORT` with the value `8080`.
+    /// The value can be quoted like any other parameter value.
+    /// A token without `=` or starting with `=` stays an argument, even if another `=` follows.
+    /// `\=` is an `=` that doesn't split the token, so `!run a\=b` has the argument `a=b`.
+    /// With [`flags_only`](Parser::flags_only) the `=` fails parsing with [`ParseError::ParametersNotAllowed`].
+    ///
+    /// Defaults to `false`.
+    pub bare_key_value: bool,
+    /// Rejects a parameter whose key was already given, instead of letting the last value win.
+    ///
+    /// When set, parsing fails with [`ParseError::DuplicateParameter`] at the start of the second parameter.
+    ///
+    /// Defaults to `false`.
+    pub reject_duplicate_parameters: bool,
+    /// Treats parameter keys that only differ in case as the same key.
+    ///
+    /// When set, keys are stored in lowercase, so `-Name:Alice -name:Bob` has the single parameter `name`
+    /// with the value `Bob`. As with any repeated key the last one wins,
+    /// and [`Command::key_casing()`] keeps the spelling it was written with.
+    /// Methods that don't return a [`Command`], like [`parse_cow`](Parser::parse_cow) or
+    /// [`parse_flags`](Parser::parse_flags), only have the lowercased keys.
+    /// [`Command::param_ci`] looks up keys regardless of this setting.
+    ///
+    /// Defaults to `false`.
+    pub case_insensitive_keys: bool,
+    /// Requires a space between an unquoted name and the first token.
+    ///
+    /// When set, a quote or the option prefix inside an unquoted name fails parsing with [`ParseError::NameError`],
+    /// so `!foo"bar"` has to be written as `!foo "bar"`.
+    /// When not set, they are ordinary characters of the name.
+    ///
+    /// Defaults to `false`.
+    pub strict_separators: bool,
+    /// Lets `\` start an escape sequence in an unquoted name, following the same rules as in quoted text.
+    ///
+    /// When set, `!fo\\o` has the name `fo\o`. When not set, `\` is an ordinary character of an unquoted name,
+    /// so the same input has the name `fo\\o`. Quoted names always use escape sequences.
+    ///
+    /// Defaults to `false`.
+    pub escape_in_name: bool,
+    /// Character that separates the arguments, options and parameters after the name.
+    ///
+    /// `' '` stands for any character accepted by [`is_whitespace`](Parser::is_whitespace).
+    /// With any other character whitespace after the name is part of the tokens,
+    /// so with `','` `!import a,b c,-opt` has the arguments `a` and `b c` and the option `opt`.
+    /// The name still ends at whitespace, and quotes can include the separator in a token, like `"a,b"`.
+    /// Repeated separators are skipped, just like repeated spaces.
+    ///
+    /// Defaults to `' '`.
+    pub arg_separator: char,
+    /// Rejects names that start with the option prefix.
+    ///
+    /// When set, `!-foo` fails with [`ParseError::NameError`] at the `-`. When not set, its name is `-foo`.
+    /// A quoted name like `!"-foo"` is always allowed.
+    ///
+    /// Defaults to `false`.
+    pub name_no_option_prefix: bool,
+    /// What happens when an option is given more than once, like `-a -a`.
+    ///
+    /// Repeats are only counted by methods that return a [`Command`]. The others, like
+    /// [`parse_cow`](Parser::parse_cow) or [`parse_flags`](Parser::parse_flags), keep each option once
+    /// with [`DuplicateMode::Count`], and [`parse_visit`](Parser::parse_visit) passes on every repeat.
+    ///
+    /// Defaults to [`DuplicateMode::KeepOne`].
+    pub duplicate_option: DuplicateMode,
 }
 
 impl Parser {
-    pub fn new(prefix: char, option_prefix: char) -> Parser {
+    /// Creates a parser with the given prefixes and everything else set to its default.
+    ///
+    /// This is a `const fn`, so a fixed configuration can be a constant.
+    ///
+    /// ```
+    /// use command_parser::Parser;
+    ///
+    /// const PARSER: Parser = Parser::new('!', '-');
+    ///
+    /// assert_eq!(PARSER.parse("!foo").unwrap().name, "foo");
+    /// ```
+    pub const fn new(prefix: char, option_prefix: char) -> Parser {
         Parser {
             prefix,
             option_prefix,
+            is_whitespace: is_space,
+            collapse_repeated_prefix: false,
+            collapse_option_prefix: false,
+            trim_name: false,
+            param_separators: Cow::Borrowed(&[':']),
+            comment: None,
+            terminator: None,
+            short_option_values: false,
+            long_options: Vec::new(),
+            raw_after_option: None,
+            value_options: Vec::new(),
+            single_quotes: false,
+            concat_fragments: false,
+            unknown_escape: UnknownEscape::Error,
+            strict: false,
+            flags_only: false,
+            max_token_len: None,
+            ignore_empty_options: false,
+            balanced_brackets: false,
+            bare_key_value: false,
+            reject_duplicate_parameters: false,
+            case_insensitive_keys: false,
+            strict_separators: false,
+            escape_in_name: false,
+            arg_separator: ' ',
+            name_no_option_prefix: false,
+            duplicate_option: DuplicateMode::KeepOne,
         }
     }
 
-    pub fn parse<'a>(&'_ self, raw: &'a str) -> Result<Command, ParseError> {
-        let mut name = String::new();
-        let mut arguments: Vec<String> = vec![];
-        let mut options: HashSet<String> = HashSet::new();
-        let mut parameters: HashMap<String, String> = HashMap::new();
-
-        let mut state = ParseState::Prefix;
-        let mut buffer = String::new();
-        let mut key_buffer = String::new();
-
-        for (cursor, c) in raw.chars().enumerate() {
-            match state {
-                ParseState::Prefix => {
-                    match c {
-                        x if x == self.prefix => {
-                            state = ParseState::Name;
-                        }
-                        _ => {return Err(PrefixError(cursor, c))}
-                    }
-                }
-                ParseState::Name => {
-                    match c {
-                        ' ' => {
-                            if cursor == 1 {
-                                return Err(NameError(cursor, c));
-                            } else {
-                                state = ParseState::Default;
-                            }
-                        }
-                        _ => { name.push(c); }
-                    }
-                }
-                ParseState::Argument => {
-                    match c {
-                        ' ' => {
-                            arguments.push(buffer);
-                            buffer = String::new();
-                            state = ParseState::Default;
-                        }
-                        _ => {
-                            buffer.push(c);
-                        }
-                    }
-                }
-                ParseState::LongArgument => {
-                    match c {
-                        '"' => {
-                            arguments.push(buffer);
-                            buffer = String::new();
-                            state = ParseState::Default;
-                        }
-                        '\\' => {
-                            state = ParseState::EscapeLongArg;
-                        }
-                        _ => {
-                            buffer.push(c);
-                        }
-                    }
-                }
-                ParseState::EscapeLongArg => {
-                    match c {
-                        '"' | '\\' => {
-                            state = ParseState::LongArgument;
-                            buffer.push(c);
-                        }
-                        _ => {
-                            return Err(EscapeError(cursor, c));
-                        }
-                    }
-                }
-                ParseState::Option => {
-                    match c {
-                        ' ' => {
-                            options.insert(buffer);
-                            buffer = String::new();
-                            state = ParseState::Default;
-                        }
-                        ':' => {
-                            key_buffer = buffer;
-                            buffer = String::new();
-                            state = ParseState::ParamConnector;
-                        }
-                        _ => {
-                            buffer.push(c);
-                        }
-                    }
-                }
-                ParseState::ParamConnector => {
-                    match c {
-                        '"' => {
-                            state = ParseState::ParamLongVal;
-                        }
-                        ' ' => {
-                            parameters.insert(key_buffer, buffer);
-                            key_buffer = String::new();
-                            buffer = String::new();
-                            state = ParseState::Default;
-                        }
-                        _ => {
-                            state = ParseState::ParamVal;
-                            buffer.push(c);
-                        }
-                    }
-                }
-                ParseState::ParamVal => {
-                    match c {
-                        ' ' => {
-                            parameters.insert(key_buffer, buffer);
-                            key_buffer = String::new();
-                            buffer = String::new();
-                            state = ParseState::Default;
-                        }
-                        _ => {
-                            buffer.push(c);
-                        }
-                    }
-                }
-                ParseState::ParamLongVal => {
-                    match c {
-                        '"' => {
-                            parameters.insert(key_buffer, buffer);
-                            key_buffer = String::new();
-                            buffer = String::new();
-                            state = ParseState::Default;
-                        }
-                        '\\' => {
-                            state = ParseState::EscapeLongParamVal;
-                        }
-                        _ => {
-                            buffer.push(c);
-                        }
-                    }
-                }
-                ParseState::EscapeLongParamVal => {
-                    match c {
-                        '"' | '\\' => {
-                            state = ParseState::ParamLongVal;
-                            buffer.push(c);
-                        }
-                        _ => {
-                            return Err(EscapeError(cursor, c));
-                        }
+    /// Like [`new`](Parser::new), but rejects an option prefix that can't be told apart from other syntax.
+    ///
+    /// ```
+    /// use command_parser::{ConfigError, Parser};
+    ///
+    /// assert!(Parser::try_new('!', '-').is_ok());
+    /// assert_eq!(Parser::try_new('!', '"').unwrap_err(), ConfigError::OptionPrefixIsQuote('"'));
+    /// ```
+    pub fn try_new(prefix: char, option_prefix: char) -> Result<Parser, ConfigError> {
+        let parser = Parser::new(prefix, option_prefix);
+        parser.validate()?;
+        Ok(parser)
+    }
+
+    /// Checks the current configuration, for example after changing [`single_quotes`](Parser::single_quotes).
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let c = self.option_prefix;
+
+        if c == '"' || (c == '\'' && self.single_quotes) {
+            return Err(ConfigError::OptionPrefixIsQuote(c));
+        }
+
+        if (self.is_whitespace)(c) {
+            return Err(ConfigError::OptionPrefixIsWhitespace(c));
+        }
+
+        Ok(())
+    }
+
+    /// Like [`parse`](Parser::parse), but returns `Ok(None)` if `raw` doesn't start with the prefix.
+    ///
+    /// This separates messages that aren't commands at all from commands that are malformed.
+    ///
+    /// ```
+    /// use command_parser::Parser;
+    ///
+    /// let p = Parser::new('!', '-');
+    ///
+    /// assert!(p.try_parse("just a normal sentence").unwrap().is_none());
+    /// assert!(p.try_parse("!foo bar").unwrap().is_some());
+    /// assert!(p.try_parse(r#"!foo "bar"#).is_err());
+    /// ```
+    pub fn try_parse(&self, raw: &str) -> Result<Option<Command>, ParseError> {
+        match raw.chars().next() {
+            Some(c) if c == self.prefix => self.parse(raw).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Removes the `\` from a message that starts with an escaped prefix.
+    ///
+    /// Any other message is returned unchanged.
+    ///
+    /// ```
+    /// use command_parser::Parser;
+    ///
+    /// let p = Parser::new('!', '-');
+    ///
+    /// assert_eq!(p.strip_escape(r"\!foo"), "!foo");
+    /// assert_eq!(p.strip_escape(r"\foo"), r"\foo");
+    /// assert_eq!(p.strip_escape("!foo"), "!foo");
+    /// ```
+    pub fn strip_escape<'a>(&self, raw: &'a str) -> &'a str {
+        match raw.strip_prefix('\\') {
+            Some(rest) if rest.starts_with(self.prefix) => rest,
+            _ => raw,
+        }
+    }
+
+    /// Parses `raw` into a [`Command`].
+    ///
+    /// Takes any string type, like `&str`, `String` or `Cow<str>`.
+    /// The command doesn't borrow from `raw`, see [`parse_cow`](Parser::parse_cow) for that.
+    pub fn parse<S: AsRef<str>>(&self, raw: S) -> Result<Command, ParseError> {
+        let raw = raw.as_ref();
+
+        // most messages aren't commands, so reject those before setting anything up
+        match raw.chars().next() {
+            Some(c) if c != self.prefix => Err(PrefixError(Span::new(0, 0), c)),
+            _ => self.build(Tokenizer::new(self, raw)),
+        }
+    }
+
+    /// Like [`parse`](Parser::parse), but borrows the text of the command from `raw` where possible.
+    ///
+    /// ```
+    /// use std::borrow::Cow;
+    /// use command_parser::Parser;
+    ///
+    /// let p = Parser::new('!', '-');
+    /// let command = p.parse_cow(r#"!foo arg1 "arg \"2\"""#).unwrap();
+    ///
+    /// assert!(matches!(command.arguments[0], Cow::Borrowed("arg1")));
+    /// assert!(matches!(command.arguments[1], Cow::Owned(_)));
+    /// ```
+    pub fn parse_cow<'a>(&self, raw: &'a str) -> Result<CommandCow<'a>, ParseError> {
+        let mut command = CommandCow {
+            prefix: self.prefix,
+            option_prefix: self.option_prefix,
+            ..Default::default()
+        };
+
+        for token in Tokenizer::new(self, raw) {
+            command.push_token(token?);
+        }
+
+        Ok(command)
+    }
+
+    /// Like [`parse`](Parser::parse), but shares the name, options and parameter keys through `interner`.
+    ///
+    /// Commands that are parsed over and over then don't allocate a new copy of their name each time.
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use command_parser::{Parser, StringInterner};
+    ///
+    /// let p = Parser::new('!', '-');
+    /// let mut interner = StringInterner::new();
+    ///
+    /// let a = p.parse_interned("!foo arg1", &mut interner).unwrap();
+    /// let b = p.parse_interned("!foo arg2", &mut interner).unwrap();
+    ///
+    /// assert!(Arc::ptr_eq(&a.name, &b.name));
+    /// ```
+    pub fn parse_interned(&self, raw: &str, interner: &mut StringInterner) -> Result<InternedCommand, ParseError> {
+        let command = self.parse_cow(raw)?;
+
+        Ok(InternedCommand {
+            prefix: command.prefix,
+            option_prefix: command.option_prefix,
+            name: interner.intern(&command.name),
+            arguments: command.arguments.into_iter().map(Cow::into_owned).collect(),
+            options: command.options.iter().map(|opt| interner.intern(opt)).collect(),
+            parameters: command.parameters.into_iter()
+                .map(|(key, val)| (interner.intern(&key), val.into_owned()))
+                .collect(),
+        })
+    }
+
+    /// Like [`parse`](Parser::parse), but passes the options and parameter keys through `interner`
+    /// so they can be shared between commands.
+    ///
+    /// Unlike [`parse_interned`](Parser::parse_interned) the name stays a `String`,
+    /// and `interner` can be any function, like one backed by a global cache.
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use command_parser::{Parser, StringInterner};
+    ///
+    /// let p = Parser::new('!', '-');
+    /// let mut interner = StringInterner::new();
+    /// let mut intern = |s: &str| interner.intern(s);
+    ///
+    /// let a = p.parse_with_key_interner("!foo -tag:a", &mut intern).unwrap();
+    /// let b = p.parse_with_key_interner("!bar -tag:b", &mut intern).unwrap();
+    ///
+    /// assert!(Arc::ptr_eq(a.parameters.keys().next().unwrap(), b.parameters.keys().next().unwrap()));
+    /// ```
+    pub fn parse_with_key_interner(
+        &self,
+        raw: &str,
+        interner: &mut impl FnMut(&str) -> Arc<str>,
+    ) -> Result<KeyInternedCommand, ParseError> {
+        let command = self.parse_cow(raw)?;
+
+        Ok(KeyInternedCommand {
+            prefix: command.prefix,
+            option_prefix: command.option_prefix,
+            name: command.name.into_owned(),
+            arguments: command.arguments.into_iter().map(Cow::into_owned).collect(),
+            options: command.options.iter().map(|opt| interner(opt)).collect(),
+            parameters: command.parameters.into_iter()
+                .map(|(key, val)| (interner(&key), val.into_owned()))
+                .collect(),
+        })
+    }
+
+    /// Lazily parses one command per line of `reader`.
+    ///
+    /// Blank lines are skipped and a malformed line only yields [`ParseLinesError::Parse`] for itself.
+    /// Lines that aren't valid UTF-8 are decoded lossily, so invalid bytes become `U+FFFD`.
+    /// An I/O error is yielded as [`ParseLinesError::Io`] and ends the iterator,
+    /// so a failed read can be told apart from the end of input.
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use command_parser::Parser;
+    ///
+    /// let p = Parser::new('!', '-');
+    /// let names: Vec<String> = p.parse_lines(Cursor::new("!foo\n\n!bar -opt\n"))
+    ///     .map(|command| command.unwrap().name)
+    ///     .collect();
+    ///
+    /// assert_eq!(names, vec!["foo", "bar"]);
+    /// ```
+    pub fn parse_lines<'p, R>(&'p self, reader: R) -> impl Iterator<Item = Result<Command, ParseLinesError>> + 'p
+    where
+        R: BufRead + 'p,
+    {
+        let mut lines = reader.split(b'\n');
+        let mut failed = false;
+
+        iter::from_fn(move || loop {
+            if failed {
+                return None;
+            }
+
+            match lines.next()? {
+                Ok(line) => {
+                    let line = String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(&line)).into_owned();
+
+                    if !line.trim().is_empty() {
+                        return Some(self.parse(&line).map_err(ParseLinesError::Parse));
                     }
                 }
-                ParseState::Default => {
-                    match c {
-                        ' ' => {}
-                        '"' => {state = ParseState::LongArgument;}
-                        x if x == self.option_prefix => {
-                            state = ParseState::Option;
-                        }
-                        _ => {
-                            state = ParseState::Argument;
-                            buffer.push(c);
-                        }
-                    }
+                Err(e) => {
+                    failed = true;
+                    return Some(Err(ParseLinesError::Io(e)));
                 }
             }
+        })
+    }
+
+    /// Parses `raw` into caller-provided collections and returns the name.
+    ///
+    /// The collections are cleared first, so their allocations can be reused across many parses.
+    /// Their contents are unspecified if parsing fails.
+    ///
+    /// ```
+    /// use std::collections::{HashMap, HashSet};
+    /// use command_parser::Parser;
+    ///
+    /// let p = Parser::new('!', '-');
+    /// let (mut args, mut opts, mut params) = (Vec::new(), HashSet::new(), HashMap::new());
+    ///
+    /// let name = p.parse_with_buffers("!foo arg -opt", &mut args, &mut opts, &mut params).unwrap();
+    ///
+    /// assert_eq!(name, "foo");
+    /// assert_eq!(args, vec!["arg"]);
+    /// ```
+    pub fn parse_with_buffers(
+        &self,
+        raw: &str,
+        args: &mut Vec<String>,
+        opts: &mut HashSet<String>,
+        params: &mut HashMap<String, String>
+    ) -> Result<String, ParseError> {
+        args.clear();
+        opts.clear();
+        params.clear();
+
+        let mut name = String::new();
+
+        for token in Tokenizer::new(self, raw) {
+            match token? {
+                Token::Name(n) => { name = n.into_owned(); }
+                Token::Argument(arg, _) => { args.push(arg.into_owned()); }
+                Token::Option(opt) => { opts.insert(opt.into_owned()); }
+                Token::Parameter(key, val, _) => { params.insert(key.into_owned(), val.into_owned()); }
+            }
         }
 
-        Ok(Command {
-            prefix: self.prefix,
-            option_prefix: self.option_prefix,
-            name, arguments, options, parameters
-        })
+        Ok(name)
     }
-}
 
+    /// Parses the first command in `raw` and returns it with the number of chars it takes up.
+    ///
+    /// The command ends at the [`terminator`](Parser::terminator), which is included in the count,
+    /// or at the end of input.
+    ///
+    /// ```
+    /// use command_parser::Parser;
+    ///
+    /// let mut p = Parser::new('!', '-');
+    /// p.terminator = Some(';');
+    ///
+    /// let (command, consumed) = p.parse_one("!a 1; !b 2").unwrap();
+    ///
+    /// assert_eq!(command.arguments, vec!["1"]);
+    /// assert_eq!(consumed, 5);
+    /// ```
+    pub fn parse_one(&self, raw: &str) -> Result<(Command, usize), ParseError> {
+        let mut tokens = Tokenizer::new(self, raw);
+        let mut command = self.empty_command();
 
-#[cfg(test)]
-pub mod tests {
-    use std::time::{Duration, Instant};
-    use super::*;
+        while let Some(token) = tokens.next() {
+            self.push_token(&mut command, token?, &mut tokens);
+        }
 
-    #[test]
-    fn parse_test() {
-        let p = Parser::new('!', '-');
-        let command_string = r##"!foo arg1 "long arg 2" -opt -opt -key1:val1 -key2:"long val2""##;
+        let consumed = tokens.terminated_at.unwrap_or_else(|| raw.chars().count());
+        Ok((command, consumed))
+    }
 
-        let command = Command {
-            prefix: '!',
-            option_prefix: '-',
-            name: "foo".to_string(),
-            arguments: vec!["arg1".to_string(), "long arg 2".to_string()],
-            options: HashSet::from(["opt".to_string()]),
-            parameters: HashMap::from([
-                ("key1".to_string(), "val1".to_string()),
-                ("key2".to_string(), "long val2".to_string())
-            ])
-        };
+    /// Like [`parse_one`](Parser::parse_one), but fails with [`ParseError::TrailingInput`]
+    /// if anything other than whitespace follows the [`terminator`](Parser::terminator).
+    ///
+    /// ```
+    /// use command_parser::Parser;
+    ///
+    /// let mut p = Parser::new('!', '-');
+    /// p.terminator = Some(';');
+    ///
+    /// assert_eq!(p.parse_exact("!a 1; ").unwrap(), p.parse("!a 1").unwrap());
+    /// assert!(p.parse_exact("!a 1; !b 2").is_err());
+    /// ```
+    pub fn parse_exact(&self, raw: &str) -> Result<Command, ParseError> {
+        let (command, consumed) = self.parse_one(raw)?;
+        let trailing = raw.char_indices()
+            .enumerate()
+            .skip(consumed)
+            .find(|(_, (_, c))| !(self.is_whitespace)(*c));
 
-        assert_eq!(p.parse(command_string).unwrap(), command);
+        match trailing {
+            Some((cursor, (byte, _))) => Err(ParseError::TrailingInput(Span::new(cursor, byte))),
+            None => Ok(command),
+        }
     }
 
-    #[test]
-    fn time_test() {
-        let p = Parser::new('!', '-');
-        let command_string = r##"!foo arg1 "long arg 2" -opt -opt -key1:val1 -key2:"long val2""##;
+    /// Parses a command whose prefix was already stripped, so `raw` starts with the name.
+    ///
+    /// [`Command::prefix`] is still set to the prefix of this parser.
+    ///
+    /// ```
+    /// use command_parser::Parser;
+    ///
+    /// let p = Parser::new('!', '-');
+    ///
+    /// assert_eq!(p.parse_no_prefix("foo arg1 -opt").unwrap(), p.parse("!foo arg1 -opt").unwrap());
+    /// ```
+    pub fn parse_no_prefix(&self, raw: &str) -> Result<Command, ParseError> {
+        self.build(Tokenizer::without_prefix(self, raw))
+    }
 
-        let now = Instant::now();
+    /// Parses a fragment that only consists of options and parameters, without prefix, name or arguments.
+    ///
+    /// Fails with [`ParseError::UnexpectedArgument`] at the start of the first argument.
+    ///
+    /// ```
+    /// use command_parser::Parser;
+    ///
+    /// let p = Parser::new('!', '-');
+    /// let (options, parameters) = p.parse_flags("-opt -key:val").unwrap();
+    ///
+    /// assert!(options.contains("opt"));
+    /// assert_eq!(parameters.get("key"), Some(&"val".to_string()));
+    /// ```
+    pub fn parse_flags(&self, raw: &str) -> Result<(HashSet<String>, HashMap<String, String>), ParseError> {
+        let mut tokens = Tokenizer::fragment(self, raw);
+        let mut options = HashSet::new();
+        let mut parameters = HashMap::new();
 
-        for _ in 0..100000 {
-            let _ = p.parse(command_string);
+        while let Some(token) = tokens.next() {
+            match token? {
+                Token::Option(opt) => { options.insert(opt.into_owned()); }
+                Token::Parameter(key, val, _) => { parameters.insert(key.into_owned(), val.into_owned()); }
+                Token::Name(_) | Token::Argument(..) => {
+                    return Err(ParseError::UnexpectedArgument(tokens.span_at(tokens.token_span.start)));
+                }
+            }
         }
 
-        println!("{}", now.elapsed().as_micros());
+        Ok((options, parameters))
+    }
 
-        let p = Parser::new('!', '-');
-        let command_string = r##"just a normal sentence"##;
+    /// Parses a string without prefix and name, like a line of a config file, into a command with an empty name.
+    ///
+    /// Arguments are allowed, unlike with [`parse_flags`](Parser::parse_flags).
+    /// Fails with [`ParseError::UnexpectedPrefix`] if `raw` starts with the prefix, which is most likely a complete command.
+    ///
+    /// ```
+    /// use command_parser::Parser;
+    ///
+    /// let p = Parser::new('!', '-');
+    /// let command = p.parse_options_only("-debug -level:3").unwrap();
+    ///
+    /// assert!(command.name.is_empty());
+    /// assert!(command.options.contains("debug"));
+    /// assert_eq!(command.parameters.get("level"), Some(&"3".to_string()));
+    /// ```
+    pub fn parse_options_only(&self, raw: &str) -> Result<Command, ParseError> {
+        let mut chars = raw.char_indices().enumerate().skip_while(|(_, (_, c))| (self.is_whitespace)(*c));
 
-        let now = Instant::now();
+        if let Some((cursor, (byte, c))) = chars.next() {
+            if c == self.prefix {
+                return Err(ParseError::UnexpectedPrefix(Span::new(cursor, byte)));
+            }
+        }
 
-        for _ in 0..100000 {
-            let _ = p.parse(command_string);
+        self.build(Tokenizer::fragment(self, raw))
+    }
+
+    /// Parses a command where everything after the first `fixed_args` arguments is captured as one last argument.
+    ///
+    /// The captured rest is taken verbatim, so quotes, escapes and options in it are not interpreted.
+    /// Only the spaces separating it from the previous argument are removed.
+    /// Options and parameters in front of it are parsed as usual.
+    ///
+    /// ```
+    /// use command_parser::Parser;
+    ///
+    /// let p = Parser::new('!', '-');
+    /// let command = p.parse_with_rest(r#"!remind 5m buy "milk" -now"#, 1).unwrap();
+    ///
+    /// assert_eq!(command.arguments, vec!["5m", r#"buy "milk" -now"#]);
+    /// ```
+    pub fn parse_with_rest(&self, raw: &str, fixed_args: usize) -> Result<Command, ParseError> {
+        let mut tokens = Tokenizer::new(self, raw);
+        let mut command = self.empty_command();
+
+        while let Some(token) = tokens.next() {
+            let token = token?;
+            let done = match token {
+                Token::Name(_) => fixed_args == 0,
+                Token::Argument(_, _) => command.arguments.len() + 1 == fixed_args,
+                Token::Option(_) | Token::Parameter(_, _, _) => false,
+            };
+
+            self.push_token(&mut command, token, &mut tokens);
+
+            if done {
+                let rest = tokens.rest().trim_start_matches(self.is_whitespace);
+
+                if !rest.is_empty() {
+                    command.push_arg(rest);
+                }
+
+                break;
+            }
         }
 
-        println!("{}", now.elapsed().as_micros());
+        Ok(command)
+    }
+
+    /// Parses only the prefix and name, and stores everything after the space following the name as the only argument.
+    ///
+    /// Unlike [`parse_with_rest`](Parser::parse_with_rest) the remainder is not trimmed at all.
+    /// If there is no remainder the command has no arguments.
+    ///
+    /// ```
+    /// use command_parser::Parser;
+    ///
+    /// let p = Parser::new('!', '-');
+    /// let command = p.parse_raw_arg(r#"!note anything goes here "even quotes" -and -dashes"#).unwrap();
+    ///
+    /// assert_eq!(command.arguments, vec![r#"anything goes here "even quotes" -and -dashes"#]);
+    /// assert!(command.options.is_empty());
+    /// ```
+    pub fn parse_raw_arg(&self, raw: &str) -> Result<Command, ParseError> {
+        let mut tokens = Tokenizer::new(self, raw);
+        let mut command = self.empty_command();
+
+        if let Some(name) = tokens.next() {
+            command.push_token(name?, self.duplicate_option);
+
+            let rest = tokens.rest();
+
+            if !rest.is_empty() {
+                command.push_arg(rest);
+            }
+        }
+
+        Ok(command)
+    }
+
+    /// Parses only the prefix and name, and keeps the rest for parsing later with [`ShallowCommand::parse_tail`].
+    ///
+    /// This way a command can be rejected by its name without parsing the rest of it.
+    ///
+    /// ```
+    /// use command_parser::Parser;
+    ///
+    /// let p = Parser::new('!', '-');
+    /// let shallow = p.parse_shallow("!git commit -m:msg").unwrap();
+    ///
+    /// assert_eq!(shallow.name, "git");
+    /// assert_eq!(shallow.tail, "commit -m:msg");
+    /// assert_eq!(shallow.parse_tail(&p).unwrap(), p.parse("!git commit -m:msg").unwrap());
+    /// ```
+    pub fn parse_shallow(&self, raw: &str) -> Result<ShallowCommand, ParseError> {
+        let mut tokens = Tokenizer::new(self, raw);
+
+        let name = match tokens.next().transpose()? {
+            Some(Token::Name(name)) => name.into_owned(),
+            _ => String::new(),
+        };
+
+        // the tail starts without the closing quote, so it can't tell what may follow it
+        tokens.check_quote_end()?;
+
+        Ok(ShallowCommand {
+            name,
+            tail: tokens.rest().to_string(),
+        })
+    }
+
+    /// Parses `raw` and passes each part of the command to `visitor` as soon as it is complete,
+    /// without building a [`Command`].
+    ///
+    /// Parts before an error are still passed to `visitor`.
+    ///
+    /// ```
+    /// use command_parser::{ParseVisitor, Parser};
+    ///
+    /// struct CountOptions(usize);
+    ///
+    /// impl ParseVisitor for CountOptions {
+    ///     fn on_option(&mut self, _opt: &str) {
+    ///         self.0 += 1;
+    ///     }
+    /// }
+    ///
+    /// let p = Parser::new('!', '-');
+    /// let mut visitor = CountOptions(0);
+    /// p.parse_visit("!foo -a arg -b", &mut visitor).unwrap();
+    ///
+    /// assert_eq!(visitor.0, 2);
+    /// ```
+    pub fn parse_visit<V: ParseVisitor>(&self, raw: &str, visitor: &mut V) -> Result<(), ParseError> {
+        for token in Tokenizer::new(self, raw) {
+            match token? {
+                Token::Name(name) => visitor.on_name(&name),
+                Token::Argument(arg, _) => visitor.on_argument(&arg),
+                Token::Option(opt) => visitor.on_option(&opt),
+                Token::Parameter(key, val, _) => visitor.on_parameter(&key, &val),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Like [`parse`](Parser::parse), but reports every error in `raw` instead of stopping at the first one.
+    ///
+    /// After an error the token it occurred in is dropped and parsing resumes after the next separator
+    /// (see [`arg_separator`](Parser::arg_separator)), as if a new token started there.
+    /// If the error occurred at a separator, parsing resumes directly after it.
+    /// So every malformed token is reported once, and an error doesn't cause follow-up errors in the next tokens.
+    /// Errors at the end of input, like [`ParseError::UnterminatedQuote`], and a missing prefix end parsing.
+    ///
+    /// ```
+    /// use command_parser::Parser;
+    ///
+    /// let p = Parser::new('!', '-');
+    /// let errors = p.parse_validate(r#"!foo "a\qb" ok "c\zd""#).unwrap_err();
+    ///
+    /// assert_eq!(errors.len(), 2);
+    /// ```
+    pub fn parse_validate(&self, raw: &str) -> Result<Command, Vec<ParseError>> {
+        let mut tokens = Tokenizer::new(self, raw);
+        let mut command = self.empty_command();
+        let mut errors = vec![];
+
+        while let Some(token) = tokens.next() {
+            match token {
+                Ok(token) => self.push_token(&mut command, token, &mut tokens),
+                Err(e @ (ParseError::PrefixError(..) | ParseError::EmptyInput)) => {
+                    errors.push(e);
+                    break;
+                }
+                Err(e) => {
+                    errors.push(e);
+                    tokens.recover();
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(command)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Like [`parse`](Parser::parse), but also reports patterns that are likely mistakes.
+    ///
+    /// Warnings are returned in the order they appear in `raw`. See [`Warning`] for what is reported.
+    ///
+    /// ```
+    /// use command_parser::{Parser, Warning};
+    ///
+    /// let p = Parser::new('!', '-');
+    /// let (command, warnings) = p.parse_with_warnings("!foo - opt").unwrap();
+    ///
+    /// assert_eq!(command.arguments, vec!["opt"]);
+    /// assert_eq!(warnings, vec![Warning::EmptyOption]);
+    /// ```
+    pub fn parse_with_warnings(&self, raw: &str) -> Result<(Command, Vec<Warning>), ParseError> {
+        let mut command = self.empty_command();
+        let mut warnings = vec![];
+        let mut tokens = Tokenizer::new(self, raw);
+
+        while let Some(token) = tokens.next() {
+            let token = token?;
+
+            match &token {
+                Token::Option(opt) if opt.is_empty() => {
+                    warnings.push(Warning::EmptyOption);
+                }
+                Token::Option(opt) if command.options.contains(opt.as_ref()) => {
+                    warnings.push(Warning::DuplicateOption(opt.to_string()));
+                }
+                Token::Parameter(key, val, _) if val.is_empty() => {
+                    warnings.push(Warning::EmptyParameterValue(key.to_string()));
+                }
+                _ => {}
+            }
+
+            self.push_token(&mut command, token, &mut tokens);
+        }
+
+        Ok((command, warnings))
+    }
+
+    /// Like [`parse`](Parser::parse), but also returns where in `raw` each part of the command comes from.
+    ///
+    /// ```
+    /// use command_parser::Parser;
+    ///
+    /// let p = Parser::new('!', '-');
+    /// let (_, spans) = p.parse_with_spans("!foo arg -verbose").unwrap();
+    ///
+    /// assert_eq!(spans.options["verbose"], 9..17);
+    /// ```
+    pub fn parse_with_spans(&self, raw: &str) -> Result<(Command, TokenSpans), ParseError> {
+        let mut command = self.empty_command();
+        let mut spans = TokenSpans::default();
+        let mut tokens = Tokenizer::new(self, raw);
+
+        while let Some(token) = tokens.next() {
+            let token = token?;
+            spans.push_token(&token, tokens.token_span.clone());
+            self.push_token(&mut command, token, &mut tokens);
+        }
+
+        Ok((command, spans))
+    }
+
+    pub(crate) fn build(&self, mut tokens: Tokenizer) -> Result<Command, ParseError> {
+        let mut command = self.empty_command();
+
+        while let Some(token) = tokens.next() {
+            self.push_token(&mut command, token?, &mut tokens);
+        }
+
+        Ok(command)
+    }
+
+    /// Adds `token` to `command`, counting repeated options and keeping the spelling of keys that `tokens` lowercased.
+    ///
+    /// Every entry point that builds a [`Command`] goes through this, so they agree on
+    /// [`Command::option_counts()`] and [`Command::key_casing()`].
+    fn push_token<'a>(&self, command: &mut Command, token: Token<'a>, tokens: &mut Tokenizer<'_, 'a>) {
+        let casing = match (&token, tokens.original_key.take()) {
+            (Token::Parameter(key, _, _), Some(original)) => Some((key.to_string(), original)),
+            _ => None,
+        };
+
+        command.push_token(token, self.duplicate_option);
+
+        if let Some((key, original)) = casing {
+            command.key_casing.insert(key, original);
+        }
+    }
+
+    fn empty_command(&self) -> Command {
+        Command::new(self.prefix, self.option_prefix, String::new(), vec![], HashSet::new(), HashMap::new())
+    }
+
+    /// Parses a command that is still being typed and describes where the input ends.
+    ///
+    /// Unlike [`parse`](Parser::parse) the token at the end of `raw` is not completed,
+    /// even if it has an open quote, but reported as [`ParseProgress::partial`].
+    ///
+    /// ```
+    /// use command_parser::{Parser, PartialKind};
+    ///
+    /// let p = Parser::new('!', '-');
+    /// let progress = p.parse_prefix(r#"!foo -key:"hal"#);
+    /// let partial = progress.partial.unwrap();
+    ///
+    /// assert_eq!(progress.command.name, "foo");
+    /// assert_eq!(partial.kind, PartialKind::ParameterValue("key".to_string()));
+    /// assert_eq!(partial.text, "hal");
+    /// assert_eq!(partial.closing_quote, Some('"'));
+    /// ```
+    pub fn parse_prefix(&self, raw: &str) -> ParseProgress {
+        let mut tokens = Tokenizer::new(self, raw);
+        let mut command = self.empty_command();
+
+        while let Some(token) = tokens.advance() {
+            match token {
+                Ok(token) => self.push_token(&mut command, token, &mut tokens),
+                Err(e) => return ParseProgress { command, partial: None, error: Some(e) },
+            }
+        }
+
+        ParseProgress { command, partial: tokens.partial(), error: None }
+    }
+
+    /// Describes the syntax this parser accepts in EBNF, using the configured characters.
+    ///
+    /// The description is meant for help pages and documentation tools.
+    /// It covers prefixes, separators, quotes, escapes, comments and terminators,
+    /// but not settings that only change how tokens are interpreted, like [`short_option_values`](Parser::short_option_values).
+    ///
+    /// ```
+    /// use command_parser::Parser;
+    ///
+    /// let grammar = Parser::new('?', '+').grammar_ebnf();
+    ///
+    /// assert!(grammar.contains(r#"command = "?" name"#));
+    /// assert!(grammar.contains(r#"option = "+" key ;"#));
+    /// ```
+    pub fn grammar_ebnf(&self) -> String {
+        let quotes: Vec<char> = if self.single_quotes { vec!['"', '\''] } else { vec!['"'] };
+        let alternatives = |chars: &[char]| chars.iter().map(|&c| terminal(c)).collect::<Vec<_>>().join(" | ");
+
+        let mut end = String::new();
+        let mut rules = vec![];
+
+        if let Some(c) = self.terminator {
+            end = format!(" [ {} ]", terminal(c));
+        }
+
+        if let Some(c) = self.comment {
+            end = format!(" [ comment ]{}", end);
+            rules.push(format!("comment = {} {{ character }} ;", terminal(c)));
+        }
+
+        if self.bare_key_value {
+            rules.push("bare_parameter = word \"=\" [ word | quoted ] ;".to_string());
+        }
+
+        let token = if self.bare_key_value {
+            "token = bare_parameter | argument | option | parameter ;"
+        } else {
+            "token = argument | option | parameter ;"
+        };
+
+        let mut grammar = vec![
+            format!("command = {} name {{ space token }}{} ;", terminal(self.prefix), end),
+            "name = word | quoted ;".to_string(),
+            token.to_string(),
+            "argument = word | quoted ;".to_string(),
+            format!("option = {} key ;", terminal(self.option_prefix)),
+            format!("parameter = {} key separator [ word | quoted ] ;", terminal(self.option_prefix)),
+            "key = word | quoted ;".to_string(),
+            format!("separator = {} ;", alternatives(&self.param_separators)),
+        ];
+
+        let quoted = quotes.iter()
+            .map(|&q| format!("{0} {{ character - {0} - \"\\\" | escape }} {0}", terminal(q)))
+            .collect::<Vec<_>>()
+            .join(" | ");
+        let mut escapable = quotes.clone();
+        escapable.push('\\');
+
+        grammar.push(format!("quoted = {} ;", quoted));
+        grammar.push(format!("escape = \"\\\" ( {} ) ;", alternatives(&escapable)));
+        grammar.push(format!("word = character - space - {} {{ character - space }} ;", alternatives(&quotes)));
+        grammar.push("space = ? whitespace character ? ;".to_string());
+        grammar.extend(rules);
+
+        let mut ebnf = grammar.join("\n");
+        ebnf.push('\n');
+        ebnf
+    }
+
+    /// Describes how `raw` is parsed, listing its parts in the order they appear.
+    ///
+    /// Arguments are numbered starting at 1.
+    ///
+    /// ```
+    /// use command_parser::Parser;
+    ///
+    /// let p = Parser::new('!', '-');
+    ///
+    /// assert_eq!(
+    ///     p.explain("!foo bar -verbose -when:now").unwrap(),
+    ///     "name: foo; argument 1: 'bar'; option: verbose; parameter when = now."
+    /// );
+    /// ```
+    pub fn explain(&self, raw: &str) -> Result<String, ParseError> {
+        let mut parts = vec![];
+        let mut arguments = 0;
+
+        for token in Tokenizer::new(self, raw) {
+            let part = match token? {
+                Token::Name(name) => format!("name: {}", name),
+                Token::Argument(arg, _) => {
+                    arguments += 1;
+                    format!("argument {}: '{}'", arguments, arg)
+                }
+                Token::Option(opt) => format!("option: {}", opt),
+                Token::Parameter(key, val, _) => format!("parameter {} = {}", key, val),
+            };
+
+            parts.push(part);
+        }
+
+        Ok(parts.join("; ") + ".")
+    }
+
+    /// Lazily parses the arguments of `raw`, without building a [`Command`].
+    ///
+    /// The name, options and parameters are parsed but skipped.
+    /// Input is only parsed as far as arguments are taken, so errors after them aren't noticed.
+    /// After an error the iterator ends.
+    ///
+    /// ```
+    /// use command_parser::Parser;
+    ///
+    /// let p = Parser::new('!', '-');
+    /// let first: Vec<_> = p.arguments_iter("!foo a -opt b c").take(2).collect::<Result<_, _>>().unwrap();
+    ///
+    /// assert_eq!(first, vec!["a", "b"]);
+    /// ```
+    pub fn arguments_iter<'a>(&'a self, raw: &'a str) -> impl Iterator<Item = Result<Cow<'a, str>, ParseError>> + 'a {
+        Tokenizer::new(self, raw).filter_map(|token| match token {
+            Ok(Token::Argument(arg, _)) => Some(Ok(arg)),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        })
+    }
+
+    /// Checks whether `raw` contains the option `option` without building a [`Command`].
+    ///
+    /// Scanning stops as soon as the option is found,
+    /// so errors further back in the input are not reported in that case.
+    ///
+    /// ```
+    /// use command_parser::Parser;
+    ///
+    /// let p = Parser::new('!', '-');
+    ///
+    /// assert!(p.has_option("!foo arg1 -verbose", "verbose").unwrap());
+    /// assert!(!p.has_option("!foo arg1 -verbose:1", "verbose").unwrap());
+    /// ```
+    pub fn has_option(&self, raw: &str, option: &str) -> Result<bool, ParseError> {
+        for token in Tokenizer::new(self, raw) {
+            if let Token::Option(opt) = token? {
+                if opt == option {
+                    return Ok(true);
+                }
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use std::borrow::Cow;
+    use std::io::Cursor;
+    use std::sync::Arc;
+    use std::time::Instant;
+    use super::*;
+    use crate::error::ParseErrorKind;
+    use crate::command::QuoteStyle;
+    use crate::progress::{PartialKind, PartialToken};
+    use crate::error::ParseError::{
+        DanglingEscape, EmptyInput, EscapeError, NameError, ParametersNotAllowed, TokenTooLong, DuplicateOption, DuplicateParameter, TrailingInput, UnexpectedArgument, UnexpectedPrefix, UnexpectedCharacter, UnterminatedQuote
+    };
+
+    #[test]
+    fn parse_test() {
+        let p = Parser::new('!', '-');
+        let command_string = r##"!foo arg1 "long arg 2" -opt -opt -key1:val1 -key2:"long val2""##;
+
+        let command = Command {
+            prefix: '!',
+            option_prefix: '-',
+            name: "foo".to_string(),
+            arguments: vec!["arg1".to_string(), "long arg 2".to_string()],
+            options: HashSet::from(["opt".to_string()]),
+            parameters: HashMap::from([
+                ("key1".to_string(), "val1".to_string()),
+                ("key2".to_string(), "long val2".to_string())
+            ]),
+            ..Default::default()
+        };
+
+        assert_eq!(p.parse(command_string).unwrap(), command);
+    }
+
+    #[test]
+    fn time_test() {
+        let p = Parser::new('!', '-');
+        let command_string = r##"!foo arg1 "long arg 2" -opt -opt -key1:val1 -key2:"long val2""##;
+
+        let now = Instant::now();
+
+        for _ in 0..100000 {
+            let _ = p.parse(command_string);
+        }
+
+        println!("{}", now.elapsed().as_micros());
+
+        let p = Parser::new('!', '-');
+        let command_string = r##"just a normal sentence"##;
+
+        let now = Instant::now();
+
+        for _ in 0..100000 {
+            let _ = p.parse(command_string);
+        }
+
+        println!("{}", now.elapsed().as_micros());
+    }
+
+    #[test]
+    fn not_a_command_allocations_test() {
+        let p = Parser::new('!', '-');
+        let sentence = "just a normal sentence that happens to be a bit longer than most commands";
+
+        assert!(matches!(p.parse(sentence), Err(PrefixError(Span { char_start: 0, .. }, 'j'))));
+
+        // the prefix check bails out before any collection is created
+        assert_eq!(crate::testing::allocations(|| { let _ = p.parse(sentence); }), 0);
+        assert_eq!(crate::testing::allocations(|| { let _ = p.try_parse(sentence); }), 0);
+        let indented = format!("  {}", sentence);
+        assert_eq!(crate::testing::allocations(|| { let _ = p.parse(&indented); }), 0);
+
+        // a command really builds them
+        assert!(crate::testing::allocations(|| { let _ = p.parse("!just a command -with -some:options"); }) > 3);
+    }
+
+    #[test]
+    fn escape_time_test() {
+        let mut p = Parser::new('!', '-');
+        p.escape_in_name = true;
+        let escaped = r"\\".repeat(100_000);
+        let unescaped = r"\".repeat(100_000);
+
+        let now = Instant::now();
+
+        let command = p.parse(format!(r#"!{0} "{0}" -k:"{0}""#, escaped)).unwrap();
+        assert_eq!(command.name, unescaped);
+        assert_eq!(command.arguments, vec![unescaped.clone()]);
+        assert_eq!(command.parameters.get("k"), Some(&unescaped));
+
+        assert!(matches!(p.parse(format!(r#"!foo "{}\"#, escaped)), Err(DanglingEscape(Span { char_start: 200_006, .. }))));
+
+        let elapsed = now.elapsed();
+        println!("escapes: {}", elapsed.as_micros());
+
+        // linear in the input, so this stays far below the bound even in debug builds
+        assert!(elapsed.as_secs() < 5);
+    }
+
+    #[test]
+    fn has_option_test() {
+        let p = Parser::new('!', '-');
+
+        assert!(p.has_option(r#"!foo arg1 "long arg 2" -opt -key:val"#, "opt").unwrap());
+        assert!(!p.has_option(r#"!foo arg1 "long arg 2" -opt -key:val"#, "key").unwrap());
+        assert!(!p.has_option("!foo -opt", "op").unwrap());
+        assert!(p.has_option(r#"!foo -opt "unterminated"#, "opt").unwrap());
+        assert!(p.has_option(r#"!foo "unterminated -opt"#, "opt").is_err());
+    }
+
+    #[test]
+    fn has_option_time_test() {
+        let p = Parser::new('!', '-');
+        let command_string = r##"!foo -opt arg1 "long arg 2" -key1:val1 -key2:"long val2" arg3 arg4"##;
+
+        let now = Instant::now();
+
+        for _ in 0..100000 {
+            let _ = p.has_option(command_string, "opt");
+        }
+
+        println!("has_option: {}", now.elapsed().as_micros());
+
+        let now = Instant::now();
+
+        for _ in 0..100000 {
+            let _ = p.parse(command_string).map(|command| command.options.contains("opt"));
+        }
+
+        println!("parse: {}", now.elapsed().as_micros());
+    }
+
+    #[test]
+    fn trailing_token_test() {
+        let p = Parser::new('!', '-');
+
+        let command = p.parse("!foo arg1 -opt -key:val arg2").unwrap();
+        assert_eq!(command.arguments, vec!["arg1", "arg2"]);
+
+        let command = p.parse("!foo -opt").unwrap();
+        assert!(command.options.contains("opt"));
+
+        let command = p.parse("!foo -key:val").unwrap();
+        assert_eq!(command.parameters.get("key"), Some(&"val".to_string()));
+    }
+
+    #[test]
+    fn empty_param_value_test() {
+        let p = Parser::new('!', '-');
+
+        let command = p.parse("!foo -key:").unwrap();
+        assert_eq!(command.parameters.get("key"), Some(&String::new()));
+        assert!(command.options.is_empty());
+
+        let command = p.parse("!foo -key").unwrap();
+        assert!(command.options.contains("key"));
+        assert!(command.parameters.is_empty());
+
+        let command = p.parse("!foo -key: ").unwrap();
+        assert_eq!(command.parameters.get("key"), Some(&String::new()));
+        assert!(command.options.is_empty());
+
+        let command = p.parse(r#"!foo -"a key": arg"#).unwrap();
+        assert_eq!(command.parameters.get("a key"), Some(&String::new()));
+        assert_eq!(command.arguments, vec!["arg"]);
+    }
+
+    #[test]
+    fn quoted_key_test() {
+        let p = Parser::new('!', '-');
+
+        let command = p.parse(r#"!foo -"full name":Alice -"last name":"Doe Jr." -"no value""#).unwrap();
+        assert_eq!(command.parameters.get("full name"), Some(&"Alice".to_string()));
+        assert_eq!(command.parameters.get("last name"), Some(&"Doe Jr.".to_string()));
+        assert!(command.options.contains("no value"));
+
+        let command = p.parse(r#"!foo -"a b" arg"#).unwrap();
+        assert!(command.options.contains("a b"));
+        assert_eq!(command.arguments, vec!["arg"]);
+
+        assert!(matches!(p.parse(r#"!foo -"full name:Alice"#), Err(UnterminatedQuote(Span { char_start: 6, .. }))));
+    }
+
+    #[test]
+    fn escaped_prefix_test() {
+        let p = Parser::new('!', '-');
+
+        assert_eq!(p.try_parse(r"\!foo arg1").unwrap(), None);
+        assert!(matches!(p.parse(r"\!foo arg1"), Err(PrefixError(Span { char_start: 0, .. }, '\\'))));
+        assert_eq!(p.strip_escape(r"\!foo arg1"), "!foo arg1");
+        assert_eq!(p.strip_escape(r"\\!foo arg1"), r"\\!foo arg1");
+    }
+
+    #[test]
+    fn strict_test() {
+        let mut p = Parser::new('!', '-');
+
+        let command = p.parse(r#"!foo "arg"x -"opt"y"#).unwrap();
+        assert_eq!(command.arguments, vec!["arg", "x", "y"]);
+        assert!(command.options.contains("opt"));
+
+        let command = p.parse(r#"!foo -k:"v"extra"#).unwrap();
+        assert_eq!(command.parameters.get("k"), Some(&"v".to_string()));
+        assert_eq!(command.arguments, vec!["extra"]);
+
+        p.strict = true;
+
+        assert!(matches!(p.parse(r#"!foo "arg"x"#), Err(UnexpectedCharacter(Span { char_start: 10, .. }, 'x'))));
+        assert!(matches!(p.parse(r#"!foo -"opt"y"#), Err(UnexpectedCharacter(Span { char_start: 11, .. }, 'y'))));
+
+        assert!(matches!(p.parse(r#"!foo -k:"v"extra"#), Err(UnexpectedCharacter(Span { char_start: 11, .. }, 'e'))));
+
+        let command = p.parse(r#"!foo -k:"v" -j:"w"-opt"#).unwrap();
+        assert_eq!(command.parameters_sorted(), vec![("j", "w"), ("k", "v")]);
+        assert!(command.options.contains("opt"));
+
+        let command = p.parse(r#"!foo "arg" "arg"-opt -"a b":c -"d e""#).unwrap();
+        assert_eq!(command.arguments, vec!["arg", "arg"]);
+        assert!(command.options.contains("opt"));
+        assert!(command.options.contains("d e"));
+        assert_eq!(command.parameters.get("a b"), Some(&"c".to_string()));
+    }
+
+    #[test]
+    fn param_separators_test() {
+        let mut p = Parser::new('!', '-');
+        p.param_separators = Cow::Borrowed(&[':', '=']);
+
+        let command = p.parse(r#"!foo -k=v -l:w -url:"a=b" -eq="c:d" -"e f"=g"#).unwrap();
+        assert_eq!(command.parameters.get("k"), Some(&"v".to_string()));
+        assert_eq!(command.parameters.get("l"), Some(&"w".to_string()));
+        assert_eq!(command.parameters.get("url"), Some(&"a=b".to_string()));
+        assert_eq!(command.parameters.get("eq"), Some(&"c:d".to_string()));
+        assert_eq!(command.parameters.get("e f"), Some(&"g".to_string()));
+
+        let command = Parser::new('!', '-').parse("!foo -k=v").unwrap();
+        assert!(command.options.contains("k=v"));
+    }
+
+    #[test]
+    fn parse_no_prefix_test() {
+        let p = Parser::new('!', '-');
+
+        let command = p.parse_no_prefix("foo arg1 -opt").unwrap();
+        assert_eq!(command.prefix, '!');
+        assert_eq!(command.name, "foo");
+        assert_eq!(command.arguments, vec!["arg1"]);
+        assert!(command.options.contains("opt"));
+
+        assert!(matches!(p.parse_no_prefix(" foo"), Err(NameError(Span { char_start: 0, .. }, ' '))));
+        assert!(matches!(p.parse("! foo"), Err(NameError(Span { char_start: 1, .. }, ' '))));
+    }
+
+    #[test]
+    fn parse_with_rest_test() {
+        let p = Parser::new('!', '-');
+
+        let command = p.parse_with_rest("!remind 5m buy milk", 1).unwrap();
+        assert_eq!(command.arguments, vec!["5m", "buy milk"]);
+
+        let command = p.parse_with_rest(r#"!remind -at:home "in 5m"  buy  milk "#, 1).unwrap();
+        assert_eq!(command.arguments, vec!["in 5m", "buy  milk "]);
+        assert_eq!(command.parameters.get("at"), Some(&"home".to_string()));
+
+        let command = p.parse_with_rest(r#"!note anything "goes -here"#, 0).unwrap();
+        assert_eq!(command.arguments, vec![r#"anything "goes -here"#]);
+
+        let command = p.parse_with_rest("!remind 5m", 1).unwrap();
+        assert_eq!(command.arguments, vec!["5m"]);
+
+        let command = p.parse_with_rest("!remind", 0).unwrap();
+        assert!(command.arguments.is_empty());
+    }
+
+    #[test]
+    fn parse_with_warnings_test() {
+        let p = Parser::new('!', '-');
+
+        let (_, warnings) = p.parse_with_warnings(r#"!foo arg1 -opt -key:val -other:"x""#).unwrap();
+        assert!(warnings.is_empty());
+
+        let (command, warnings) = p.parse_with_warnings("!foo - opt").unwrap();
+        assert_eq!(command.arguments, vec!["opt"]);
+        assert_eq!(warnings, vec![Warning::EmptyOption]);
+
+        let (_, warnings) = p.parse_with_warnings(r#"!foo -key: -other:"""#).unwrap();
+        assert_eq!(warnings, vec![
+            Warning::EmptyParameterValue("key".to_string()),
+            Warning::EmptyParameterValue("other".to_string()),
+        ]);
+
+        let (_, warnings) = p.parse_with_warnings("!foo -opt -x -opt").unwrap();
+        assert_eq!(warnings, vec![Warning::DuplicateOption("opt".to_string())]);
+    }
+
+    #[test]
+    fn single_quotes_test() {
+        let mut p = Parser::new('!', '-');
+
+        let command = p.parse("!foo 'a b'").unwrap();
+        assert_eq!(command.arguments, vec!["'a", "b'"]);
+
+        p.single_quotes = true;
+
+        let command = p.parse(r#"!foo 'a "b"' "it's" 'it\'s' -'c d':'e f'"#).unwrap();
+        assert_eq!(command.arguments, vec![r#"a "b""#, "it's", "it's"]);
+        assert_eq!(command.parameters.get("c d"), Some(&"e f".to_string()));
+        assert!(matches!(p.parse("!foo 'a"), Err(UnterminatedQuote(Span { char_start: 5, .. }))));
+    }
+
+    #[test]
+    fn quote_style_round_trip_test() {
+        let mut p = Parser::new('!', '-');
+        p.single_quotes = true;
+
+        let command = p.parse(r#"!foo 'long arg' "other arg" plain -key:'long val' -k:v"#).unwrap();
+        assert_eq!(command.quote_styles.arguments, vec![QuoteStyle::Single, QuoteStyle::Double, QuoteStyle::Unquoted]);
+        assert_eq!(command.quote_styles.parameter("key"), QuoteStyle::Single);
+        assert_eq!(command.quote_styles.parameter("k"), QuoteStyle::Unquoted);
+
+        let displayed = command.to_string();
+        assert_eq!(displayed, r#"!foo 'long arg' "other arg" plain -k:v -key:'long val'"#);
+
+        let reparsed = p.parse(&displayed).unwrap();
+        assert_eq!(reparsed, command);
+        assert_eq!(reparsed.quote_styles, command.quote_styles);
+
+        let mut command = Parser::new('!', '-').parse("!foo arg").unwrap();
+        command.arguments[0] = "now with spaces".to_string();
+        assert_eq!(command.to_string(), r#"!foo "now with spaces""#);
+    }
+
+    #[test]
+    fn comment_test() {
+        let mut p = Parser::new('!', '-');
+
+        let command = p.parse("!set x 1 # this is a comment").unwrap();
+        assert_eq!(command.arguments, vec!["x", "1", "#", "this", "is", "a", "comment"]);
+
+        p.comment = Some('#');
+
+        let command = p.parse("!set x 1 # this is a comment").unwrap();
+        assert_eq!(command.arguments, vec!["x", "1"]);
+
+        let command = p.parse(r##"!set x "1 # not a comment" -key:"#"# -opt"##).unwrap();
+        assert_eq!(command.arguments, vec!["x", "1 # not a comment"]);
+        assert_eq!(command.parameters.get("key"), Some(&"#".to_string()));
+        assert!(command.options.is_empty());
+
+        let command = p.parse(r#"!set x -opt#"unterminated"#).unwrap();
+        assert_eq!(command.arguments, vec!["x"]);
+        assert!(command.options.contains("opt"));
+
+        let command = p.parse("!set#x").unwrap();
+        assert_eq!(command.name, "set");
+    }
+
+    #[test]
+    fn concat_fragments_test() {
+        let mut p = Parser::new('!', '-');
+        let command_string = r#"!foo abc"def"ghi "a b"c -msg:hello" world" -k:"v"w"#;
+
+        let command = p.parse(command_string).unwrap();
+        assert_eq!(command.arguments, vec![r#"abc"def"ghi"#, "a b", "c", r#"world""#, "w"]);
+        assert_eq!(command.parameters.get("msg"), Some(&r#"hello""#.to_string()));
+
+        p.concat_fragments = true;
+
+        let command = p.parse(command_string).unwrap();
+        assert_eq!(command.arguments, vec!["abcdefghi", "a bc"]);
+        assert_eq!(command.parameters.get("msg"), Some(&"hello world".to_string()));
+        assert_eq!(command.parameters.get("k"), Some(&"vw".to_string()));
+
+        let command = p.parse(r#"!foo "" "a"""b "#).unwrap();
+        assert_eq!(command.arguments, vec!["", "ab"]);
+
+        assert!(matches!(p.parse(r#"!foo abc"def"#), Err(UnterminatedQuote(Span { char_start: 8, .. }))));
+    }
+
+    #[test]
+    fn unknown_escape_test() {
+        let mut p = Parser::new('!', '-');
+        let command_string = r#"!foo "a\xb" -"k\y":"v\z""#;
+
+        assert!(matches!(p.parse(command_string), Err(EscapeError(Span { char_start: 8, .. }, 'x'))));
+
+        p.unknown_escape = UnknownEscape::Keep;
+        let command = p.parse(command_string).unwrap();
+        assert_eq!(command.arguments, vec![r"a\xb"]);
+        assert_eq!(command.parameters.get(r"k\y"), Some(&r"v\z".to_string()));
+
+        p.unknown_escape = UnknownEscape::Strip;
+        let command = p.parse(command_string).unwrap();
+        assert_eq!(command.arguments, vec!["axb"]);
+        assert_eq!(command.parameters.get("ky"), Some(&"vz".to_string()));
+
+        let command = p.parse(r#"!foo "a\"b\\c""#).unwrap();
+        assert_eq!(command.arguments, vec![r#"a"b\c"#]);
+    }
+
+    #[test]
+    fn parse_cow_test() {
+        let p = Parser::new('!', '-');
+        let command_string = r#"!foo arg1 "long arg" "esc\"aped" -opt -key:"val" -"k\\ey":v"#;
+
+        let command = p.parse_cow(command_string).unwrap();
+        assert!(matches!(command.name, Cow::Borrowed("foo")));
+        assert!(matches!(command.arguments[0], Cow::Borrowed("arg1")));
+        assert!(matches!(command.arguments[1], Cow::Borrowed("long arg")));
+        assert!(matches!(&command.arguments[2], Cow::Owned(arg) if arg == r#"esc"aped"#));
+        assert!(matches!(command.options.get("opt"), Some(Cow::Borrowed(_))));
+        assert!(matches!(command.parameters.get("key"), Some(Cow::Borrowed("val"))));
+        assert!(matches!(command.parameters.get_key_value(r"k\ey"), Some((Cow::Owned(_), Cow::Borrowed("v")))));
+
+        assert_eq!(command.into_owned(), p.parse(command_string).unwrap());
+
+        let mut p = Parser::new('!', '-');
+        p.unknown_escape = UnknownEscape::Keep;
+        let command = p.parse_cow(r#"!foo "a\xb""#).unwrap();
+        assert!(matches!(command.arguments[0], Cow::Borrowed(r"a\xb")));
+    }
+
+    #[test]
+    fn collapse_repeated_prefix_test() {
+        let mut p = Parser::new('!', '-');
+
+        assert_eq!(p.parse("!!foo arg").unwrap().name, "!foo");
+
+        p.collapse_repeated_prefix = true;
+
+        let command = p.parse("!!!foo arg").unwrap();
+        assert_eq!(command.name, "foo");
+        assert_eq!(command.arguments, vec!["arg"]);
+        assert_eq!(command, p.parse("!foo arg").unwrap());
+        assert_eq!(p.parse("!fo!o").unwrap().name, "fo!o");
+        assert!(matches!(p.parse("!! foo"), Err(NameError(Span { char_start: 2, .. }, ' '))));
+    }
+
+    #[test]
+    fn collapse_option_prefix_test() {
+        let mut p = Parser::new('!', '-');
+
+        assert!(p.parse("!foo --help").unwrap().options.contains("-help"));
+
+        p.collapse_option_prefix = true;
+
+        let command = p.parse(r#"!foo --help ---key:val -a-b --"x y" arg"#).unwrap();
+        assert_eq!(command.options_sorted(), vec!["a-b", "help", "x y"]);
+        assert_eq!(command.parameters_sorted(), vec![("key", "val")]);
+        assert_eq!(command.arguments, vec!["arg"]);
+        assert_eq!(p.parse("!foo -help").unwrap(), p.parse("!foo --help").unwrap());
+    }
+
+    #[test]
+    fn trim_name_test() {
+        let mut p = Parser::new('!', '-');
+
+        assert!(matches!(p.parse("!  foo"), Err(NameError(Span { char_start: 1, .. }, ' '))));
+
+        p.trim_name = true;
+
+        let command = p.parse("!  foo arg").unwrap();
+        assert_eq!(command, p.parse("!foo arg").unwrap());
+        assert_eq!(p.parse_no_prefix(" foo").unwrap().name, "foo");
+        assert!(matches!(p.parse(r#"!  "foo"#), Err(UnterminatedQuote(Span { char_start: 3, .. }))));
+    }
+
+    #[test]
+    fn short_option_values_test() {
+        let mut p = Parser::new('!', '-');
+        let command_string = r#"!foo -n5 -abc -v -verbose -k:v -"quoted""#;
+
+        let command = p.parse(command_string).unwrap();
+        assert!(command.options.contains("n5"));
+        assert!(command.options.contains("abc"));
+        assert!(command.parameters.contains_key("k"));
+
+        p.short_option_values = true;
+        p.long_options = vec!["verbose".to_string()];
+
+        let command = p.parse(command_string).unwrap();
+        assert_eq!(command.parameters.get("n"), Some(&"5".to_string()));
+        assert_eq!(command.parameters.get("a"), Some(&"bc".to_string()));
+        assert_eq!(command.parameters.get("k"), Some(&"v".to_string()));
+        assert_eq!(command.options_sorted(), vec!["quoted", "v", "verbose"]);
+
+        p.flags_only = true;
+        assert!(matches!(p.parse("!x -n5"), Err(ParametersNotAllowed(Span { char_start: 5, .. }))));
+        assert!(matches!(p.parse("!x -v -n5"), Err(ParametersNotAllowed(Span { char_start: 8, .. }))));
+
+        let command = p.parse("!x -v -verbose").unwrap();
+        assert_eq!(command.options_sorted(), vec!["v", "verbose"]);
+    }
+
+    #[test]
+    fn is_whitespace_test() {
+        let mut p = Parser::new('!', '-');
+        let command_string = "!foo\u{a0}arg1 arg2\u{a0}-opt\u{a0}\"long\u{a0}arg\"";
+
+        let command = p.parse(command_string).unwrap();
+        assert_eq!(command.name, "foo\u{a0}arg1");
+
+        p.is_whitespace = |c| c == ' ' || c == '\u{a0}';
+
+        let command = p.parse(command_string).unwrap();
+        assert_eq!(command.name, "foo");
+        assert_eq!(command.arguments, vec!["arg1", "arg2", "long\u{a0}arg"]);
+        assert!(command.options.contains("opt"));
+
+        p.is_whitespace = char::is_whitespace;
+
+        let command = p.parse("!foo\targ1\narg2").unwrap();
+        assert_eq!(command.arguments, vec!["arg1", "arg2"]);
+    }
+
+    #[test]
+    fn parse_prefix_test() {
+        let p = Parser::new('!', '-');
+
+        let progress = p.parse_prefix(r#"!foo arg1 -opt -key:"hal"#);
+        assert!(progress.error.is_none());
+        assert_eq!(progress.command.arguments, vec!["arg1"]);
+        assert!(progress.command.options.contains("opt"));
+        assert_eq!(progress.partial, Some(PartialToken {
+            kind: PartialKind::ParameterValue("key".to_string()),
+            text: "hal".to_string(),
+            closing_quote: Some('"'),
+        }));
+
+        let partial = p.parse_prefix("!fo").partial.unwrap();
+        assert_eq!(partial.kind, PartialKind::Name);
+        assert_eq!(partial.text, "fo");
+        assert_eq!(partial.closing_quote, None);
+
+        let partial = p.parse_prefix("!foo -ver").partial.unwrap();
+        assert_eq!(partial.kind, PartialKind::Option);
+        assert_eq!(partial.text, "ver");
+
+        let partial = p.parse_prefix(r#"!foo "long \"ar"#).partial.unwrap();
+        assert_eq!(partial.kind, PartialKind::Argument);
+        assert_eq!(partial.text, r#"long "ar"#);
+
+        assert_eq!(p.parse_prefix("!foo arg ").partial, None);
+        assert_eq!(p.parse_prefix("").partial, None);
+        assert!(matches!(p.parse_prefix("foo").error, Some(PrefixError(Span { char_start: 0, .. }, 'f'))));
+    }
+
+    #[test]
+    fn quoted_name_test() {
+        let p = Parser::new('!', '-');
+
+        let command = p.parse(r#"!"two words" arg -"say \"hi\"""#).unwrap();
+        assert_eq!(command.name, "two words");
+        assert_eq!(command.arguments, vec!["arg"]);
+
+        assert!(matches!(p.parse(r#"!"foo"#), Err(UnterminatedQuote(Span { char_start: 1, .. }))));
+        assert!(matches!(p.parse(r#"!"" arg"#), Err(NameError(Span { char_start: 2, .. }, '"'))));
+
+        assert_eq!(p.parse(r"!fo\o").unwrap().name, r"fo\o");
+        assert_eq!(p.parse(r#"!"fo\\o""#).unwrap().name, r"fo\o");
+    }
+
+    #[test]
+    fn escape_in_name_test() {
+        let mut p = Parser::new('!', '-');
+
+        let command = p.parse(r"!fo\\o arg").unwrap();
+        assert_eq!(command.name, r"fo\\o");
+        assert_eq!(p.parse(command.to_string()).unwrap(), command);
+        assert_eq!(p.parse(r"!fo\").unwrap().name, r"fo\");
+
+        p.escape_in_name = true;
+
+        let command = p.parse(r"!fo\\o arg").unwrap();
+        assert_eq!(command.name, r"fo\o");
+        assert_eq!(command.arguments, vec!["arg"]);
+        assert_eq!(p.parse(command.to_string()).unwrap(), command);
+        assert_eq!(p.parse(r#"!say\"hi\""#).unwrap().name, r#"say"hi""#);
+
+        assert!(matches!(p.parse(r"!fo\o"), Err(EscapeError(Span { char_start: 4, .. }, 'o'))));
+        assert!(matches!(p.parse(r"!fo\"), Err(DanglingEscape(Span { char_start: 3, .. }))));
+    }
+
+    #[test]
+    fn parse_with_key_interner_test() {
+        let p = Parser::new('!', '-');
+        let mut interner = StringInterner::new();
+        let mut calls = 0;
+        let mut intern = |s: &str| {
+            calls += 1;
+            interner.intern(s)
+        };
+
+        let a = p.parse_with_key_interner("!foo arg -opt -key:val1", &mut intern).unwrap();
+        let b = p.parse_with_key_interner("!foo arg -opt -key:val2", &mut intern).unwrap();
+
+        let key = |command: &KeyInternedCommand| Arc::clone(command.parameters.keys().next().unwrap());
+        assert!(Arc::ptr_eq(&key(&a), &key(&b)));
+        assert!(Arc::ptr_eq(a.options.iter().next().unwrap(), b.options.iter().next().unwrap()));
+        assert_eq!(a.parameters[&key(&a)], "val1");
+        assert_eq!(b.parameters[&key(&b)], "val2");
+        assert_eq!(a.name, "foo");
+        assert_eq!(calls, 4);
+        assert_eq!(interner.len(), 2);
+    }
+
+    #[test]
+    fn parse_interned_test() {
+        let p = Parser::new('!', '-');
+        let mut interner = StringInterner::new();
+
+        let a = p.parse_interned("!foo arg1 -opt -key:val1", &mut interner).unwrap();
+        let b = p.parse_interned("!foo arg2 -opt -key:val2", &mut interner).unwrap();
+
+        assert!(Arc::ptr_eq(&a.name, &b.name));
+        assert!(Arc::ptr_eq(a.options.iter().next().unwrap(), b.options.iter().next().unwrap()));
+        assert!(Arc::ptr_eq(a.parameters.keys().next().unwrap(), b.parameters.keys().next().unwrap()));
+        assert_eq!(b.arguments, vec!["arg2"]);
+        assert_eq!(b.parameters.get("key").unwrap(), "val2");
+        assert_eq!(interner.len(), 3);
+
+        assert!(matches!(p.parse_interned("foo", &mut interner), Err(PrefixError(Span { char_start: 0, .. }, 'f'))));
+    }
+
+    #[test]
+    fn quote_at_end_test() {
+        let p = Parser::new('!', '-');
+
+        assert!(matches!(p.parse(r#"!foo ""#), Err(UnterminatedQuote(Span { char_start: 5, .. }))));
+        assert!(matches!(p.parse(r#"!foo -""#), Err(UnterminatedQuote(Span { char_start: 6, .. }))));
+        assert!(matches!(p.parse(r#"!foo -key:""#), Err(UnterminatedQuote(Span { char_start: 10, .. }))));
+        assert!(matches!(p.parse(r#"!""#), Err(UnterminatedQuote(Span { char_start: 1, .. }))));
+
+        let command = p.parse(r#"!foo """#).unwrap();
+        assert_eq!(command.arguments, vec![""]);
+        assert_eq!(command.quote_styles.argument(0), QuoteStyle::Double);
+
+        let command = p.parse(r#"!foo -key:"""#).unwrap();
+        assert_eq!(command.parameters.get("key"), Some(&String::new()));
+    }
+
+    #[test]
+    fn flags_only_test() {
+        let mut p = Parser::new('!', '-');
+        p.flags_only = true;
+
+        let command = p.parse("!foo arg -a -b").unwrap();
+        assert_eq!(command.options_sorted(), vec!["a", "b"]);
+
+        assert!(matches!(p.parse("!foo -a -k:v"), Err(ParametersNotAllowed(Span { char_start: 10, .. }))));
+        assert!(matches!(p.parse(r#"!foo -"k k":v"#), Err(ParametersNotAllowed(Span { char_start: 11, .. }))));
+    }
+
+    #[test]
+    fn span_test() {
+        let mut p = Parser::new('!', '-');
+        p.strict = true;
+
+        let raw = r#"!föö "a"x"#;
+        let Err(UnexpectedCharacter(span, 'x')) = p.parse(raw) else { panic!() };
+        assert_eq!(span, Span::new(8, 10));
+        assert_eq!(&raw[span.byte_start..], "x");
+
+        let Err(UnterminatedQuote(span)) = p.parse(r#"!ü "ä"#) else { panic!() };
+        assert_eq!(span, Span::new(3, 4));
+
+        let Err(EscapeError(span, 'x')) = p.parse(r#"!ü "ä\x""#) else { panic!() };
+        assert_eq!(span, Span::new(6, 8));
+    }
+
+    #[test]
+    fn option_prefix_is_quote_test() {
+        assert_eq!(Parser::try_new('!', '"').unwrap_err(), ConfigError::OptionPrefixIsQuote('"'));
+        assert_eq!(Parser::try_new('!', ' ').unwrap_err(), ConfigError::OptionPrefixIsWhitespace(' '));
+
+        let mut p = Parser::try_new('!', '\'').unwrap();
+        p.single_quotes = true;
+        assert_eq!(p.validate(), Err(ConfigError::OptionPrefixIsQuote('\'')));
+
+        // the quote wins if the parser is created anyway
+        let p = Parser::new('!', '"');
+        let command = p.parse(r#"!foo "opt" "a b" "key":val"#).unwrap();
+        assert_eq!(command.arguments, vec!["opt", "a b", "key", ":val"]);
+        assert!(command.options.is_empty());
+        assert!(command.parameters.is_empty());
+        assert!(matches!(p.parse(r#"!foo "opt"#), Err(UnterminatedQuote(Span { char_start: 5, .. }))));
+    }
+
+    #[test]
+    fn parse_lines_test() {
+        let p = Parser::new('!', '-');
+        let input = Cursor::new("!foo arg1\n\n  \n!bar \"unterminated\r\n!baz -opt\r\n");
+
+        let results: Vec<Result<Command, ParseLinesError>> = p.parse_lines(input).collect();
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().arguments, vec!["arg1"]);
+        assert!(matches!(results[1], Err(ParseLinesError::Parse(UnterminatedQuote(Span { char_start: 5, .. })))));
+        assert_eq!(results[2].as_ref().unwrap().name, "baz");
+        assert!(results[2].as_ref().unwrap().options.contains("opt"));
+
+        let input = Cursor::new(b"!a\n!b \xff\n!c\n".to_vec());
+        let commands: Vec<Command> = p.parse_lines(input).map(Result::unwrap).collect();
+
+        assert_eq!(commands.iter().map(|command| command.name.as_str()).collect::<Vec<_>>(), vec!["a", "b", "c"]);
+        assert_eq!(commands[1].arguments, vec!["\u{FFFD}"]);
+
+        struct Failing;
+
+        impl std::io::Read for Failing {
+            fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("connection lost"))
+            }
+        }
+
+        let input = std::io::BufReader::new(std::io::Read::chain(Cursor::new("!a\n!b\n"), Failing));
+        let results: Vec<Result<Command, ParseLinesError>> = p.parse_lines(input).collect();
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[1].as_ref().unwrap().name, "b");
+        assert!(matches!(&results[2], Err(ParseLinesError::Io(e)) if e.to_string() == "connection lost"));
+    }
+
+    #[test]
+    fn parse_with_spans_test() {
+        let mut p = Parser::new('!', '-');
+        let raw = r#"!foo arg1 "long arg" -verbose -key:"a b" -"x y" -last"#;
+
+        let (command, spans) = p.parse_with_spans(raw).unwrap();
+        let text = |range: &std::ops::Range<usize>| -> String {
+            raw.chars().skip(range.start).take(range.len()).collect()
+        };
+
+        assert_eq!(command.arguments.len(), 2);
+        assert_eq!(text(&spans.name), "!foo");
+        assert_eq!(spans.arguments.iter().map(text).collect::<Vec<_>>(), vec!["arg1", r#""long arg""#]);
+        assert_eq!(spans.options["verbose"], 21..29);
+        assert_eq!(text(&spans.parameters["key"]), r#"-key:"a b""#);
+        assert_eq!(text(&spans.options["x y"]), r#"-"x y""#);
+        assert_eq!(text(&spans.options["last"]), "-last");
+
+        p.strict = true;
+        let (_, spans) = p.parse_with_spans(r#"!foo -"a"-b"#).unwrap();
+        assert_eq!(spans.options["a"], 5..9);
+        assert_eq!(spans.options["b"], 9..11);
+    }
+
+    #[test]
+    fn parse_raw_arg_test() {
+        let p = Parser::new('!', '-');
+
+        let command = p.parse_raw_arg(r#"!note  "unterminated -opt -key:val \x "#).unwrap();
+        assert_eq!(command.name, "note");
+        assert_eq!(command.arguments, vec![r#" "unterminated -opt -key:val \x "#]);
+        assert!(command.options.is_empty());
+        assert!(command.parameters.is_empty());
+
+        assert!(p.parse_raw_arg("!note").unwrap().arguments.is_empty());
+        assert!(matches!(p.parse_raw_arg("note"), Err(PrefixError(Span { char_start: 0, .. }, 'n'))));
+    }
+
+    #[test]
+    fn max_token_len_test() {
+        let mut p = Parser::new('!', '-');
+        p.max_token_len = Some(8);
+
+        let command = p.parse(r#"!foo "12345678" -opt12345 -key:12345678"#).unwrap();
+        assert_eq!(command.arguments, vec!["12345678"]);
+
+        let raw = format!(r#"!foo "{}""#, "a".repeat(1000));
+        assert!(matches!(p.parse(&raw), Err(TokenTooLong(Span { char_start: 14, .. }))));
+        assert!(matches!(p.parse("!foo -key:123456789"), Err(TokenTooLong(Span { char_start: 18, .. }))));
+        assert!(matches!(p.parse("!foo123456"), Err(TokenTooLong(Span { char_start: 9, .. }))));
+    }
+
+    #[test]
+    fn parse_visit_test() {
+        #[derive(Default)]
+        struct Recorder(Vec<String>);
+
+        impl ParseVisitor for Recorder {
+            fn on_name(&mut self, name: &str) {
+                self.0.push(format!("name {}", name));
+            }
+
+            fn on_argument(&mut self, arg: &str) {
+                self.0.push(format!("argument {}", arg));
+            }
+
+            fn on_option(&mut self, opt: &str) {
+                self.0.push(format!("option {}", opt));
+            }
+
+            fn on_parameter(&mut self, key: &str, val: &str) {
+                self.0.push(format!("parameter {}={}", key, val));
+            }
+        }
+
+        let p = Parser::new('!', '-');
+
+        let mut recorder = Recorder::default();
+        p.parse_visit(r#"!foo -opt arg1 -key:"a b" "arg 2" -opt"#, &mut recorder).unwrap();
+        assert_eq!(recorder.0, vec![
+            "name foo", "option opt", "argument arg1", "parameter key=a b", "argument arg 2", "option opt",
+        ]);
+
+        let mut recorder = Recorder::default();
+        assert!(p.parse_visit(r#"!foo arg "open"#, &mut recorder).is_err());
+        assert_eq!(recorder.0, vec!["name foo", "argument arg"]);
+    }
+
+    #[test]
+    fn dangling_escape_test() {
+        let p = Parser::new('!', '-');
+
+        // outside of quotes `\` doesn't escape anything
+        assert_eq!(p.parse(r"!foo bar\").unwrap().arguments, vec![r"bar\"]);
+
+        assert!(matches!(p.parse(r#"!foo "bar\"#), Err(DanglingEscape(Span { char_start: 9, .. }))));
+        assert!(matches!(p.parse(r#"!foo -"opt\"#), Err(DanglingEscape(Span { char_start: 10, .. }))));
+        assert!(matches!(p.parse(r#"!foo -key:"ä\"#), Err(DanglingEscape(Span { char_start: 12, byte_start: 13 }))));
+        assert!(matches!(p.parse(r#"!"foo\"#), Err(DanglingEscape(Span { char_start: 5, .. }))));
+    }
+
+    #[test]
+    fn ignore_empty_options_test() {
+        let mut p = Parser::new('!', '-');
+
+        assert!(p.parse("!foo - bar").unwrap().options.contains(""));
+
+        p.ignore_empty_options = true;
+
+        let command = p.parse(r#"!foo - bar -"" -opt -"#).unwrap();
+        assert_eq!(command.arguments, vec!["bar"]);
+        assert_eq!(command.options_sorted(), vec!["opt"]);
+        assert_eq!(p.parse("!foo - bar").unwrap(), p.parse("!foo bar").unwrap());
+    }
+
+    #[test]
+    fn unicode_prefix_test() {
+        let p = Parser::try_new('🤖', '➖').unwrap();
+
+        let command = p.parse("🤖foo arg ➖opt ➖key:val").unwrap();
+        assert_eq!(command.name, "foo");
+        assert_eq!(command.arguments, vec!["arg"]);
+        assert!(command.options.contains("opt"));
+        assert_eq!(command.parameters.get("key"), Some(&"val".to_string()));
+        assert_eq!(command.to_string(), "🤖foo arg ➖opt ➖key:val");
+
+        assert!(matches!(p.parse("!foo"), Err(PrefixError(Span { char_start: 0, .. }, '!'))));
+        assert!(matches!(p.try_parse("🤖foo"), Ok(Some(_))));
+        assert!(matches!(p.try_parse("🤗foo"), Ok(None)));
+
+        let Err(UnterminatedQuote(span)) = p.parse(r#"🤖foo "arg"#) else { panic!() };
+        assert_eq!(span, Span::new(5, 8));
+        assert_eq!(&r#"🤖foo "arg"#[span.byte_start..], r#""arg"#);
+    }
+
+    #[test]
+    fn parse_with_buffers_test() {
+        let p = Parser::new('!', '-');
+        let mut args = Vec::new();
+        let mut opts = HashSet::new();
+        let mut params = HashMap::new();
+
+        let name = p.parse_with_buffers("!foo a b c -x -y -k:v", &mut args, &mut opts, &mut params).unwrap();
+        assert_eq!(name, "foo");
+        assert_eq!(args, vec!["a", "b", "c"]);
+        assert_eq!(opts.len(), 2);
+        assert_eq!(params.len(), 1);
+
+        let capacity = args.capacity();
+        let name = p.parse_with_buffers("!bar d -z", &mut args, &mut opts, &mut params).unwrap();
+        assert_eq!(name, "bar");
+        assert_eq!(args, vec!["d"]);
+        assert_eq!(args.capacity(), capacity);
+        assert_eq!(opts, HashSet::from(["z".to_string()]));
+        assert!(params.is_empty());
+    }
+
+    #[test]
+    fn parse_one_test() {
+        let mut p = Parser::new('!', '-');
+        p.terminator = Some(';');
+
+        let raw = r#"!a 1 "x;y"; !b 2"#;
+        let (first, consumed) = p.parse_one(raw).unwrap();
+        assert_eq!(first.name, "a");
+        assert_eq!(first.arguments, vec!["1", "x;y"]);
+        assert_eq!(consumed, 11);
+
+        let rest: String = raw.chars().skip(consumed).collect();
+        let (second, consumed) = p.parse_one(rest.trim_start()).unwrap();
+        assert_eq!(second.name, "b");
+        assert_eq!(second.arguments, vec!["2"]);
+        assert_eq!(consumed, 4);
+
+        assert_eq!(p.parse("!a -opt;!b").unwrap().options_sorted(), vec!["opt"]);
+        assert_eq!(p.parse_one("!a").unwrap().1, 2);
+    }
+
+    #[test]
+    fn empty_input_test() {
+        let p = Parser::new('!', '-');
+
+        assert!(matches!(p.parse(""), Err(EmptyInput)));
+        assert!(matches!(p.parse_cow(""), Err(EmptyInput)));
+        assert!(matches!(p.parse_with_warnings(""), Err(EmptyInput)));
+
+        // whitespace is not empty, it just doesn't start with the prefix
+        assert!(matches!(p.parse("   "), Err(PrefixError(Span { char_start: 0, .. }, ' '))));
+        assert_eq!(p.try_parse("   ").unwrap(), None);
+    }
+
+    #[test]
+    fn prefix_in_arguments_test() {
+        let p = Parser::new('!', '-');
+
+        let command = p.parse(r#"!echo ! "!foo" !bar -! -!:! -key:"!""#).unwrap();
+        assert_eq!(command.name, "echo");
+        assert_eq!(command.arguments, vec!["!", "!foo", "!bar"]);
+        assert_eq!(command.options_sorted(), vec!["!"]);
+        assert_eq!(command.parameters_sorted(), vec![("!", "!"), ("key", "!")]);
+        assert_eq!(p.parse("!fo!o").unwrap().name, "fo!o");
+        assert_eq!(p.parse(command.to_string()).unwrap(), command);
+    }
+
+    #[test]
+    fn const_parser_test() {
+        const PARSER: Parser = Parser::new('!', '-');
+        static STATIC_PARSER: Parser = Parser::new('?', '+');
+
+        let command = PARSER.parse("!foo arg -opt -key:val").unwrap();
+        assert_eq!(command.arguments, vec!["arg"]);
+        assert_eq!(command.parameters.get("key"), Some(&"val".to_string()));
+        assert_eq!(STATIC_PARSER.parse("?foo +opt").unwrap().options_sorted(), vec!["opt"]);
+    }
+
+    #[test]
+    fn balanced_brackets_test() {
+        let mut p = Parser::new('!', '-');
+        let raw = r#"!foo -data:"{"a":[1, "b"]}" "[x]" arg"#;
+
+        assert_eq!(p.parse(raw).unwrap().parameters.get("data"), Some(&"{".to_string()));
+
+        p.balanced_brackets = true;
+
+        let command = p.parse(raw).unwrap();
+        assert_eq!(command.parameters.get("data"), Some(&r#"{"a":[1, "b"]}"#.to_string()));
+        assert_eq!(command.arguments, vec!["[x]", "arg"]);
+        assert_eq!(p.parse(command.to_string()).unwrap(), command);
+
+        assert!(matches!(p.parse(r#"!foo -data:"{"a":1" arg"#), Err(UnterminatedQuote(Span { char_start: 11, .. }))));
+        assert_eq!(p.parse(r#"!foo "}" "a]""#).unwrap().arguments, vec!["}", "a]"]);
+    }
+
+    #[test]
+    fn grammar_ebnf_test() {
+        let mut p = Parser::new('$', '~');
+
+        let grammar = p.grammar_ebnf();
+        assert!(grammar.starts_with("command = \"$\" name { space token } ;\n"));
+        assert!(grammar.contains("option = \"~\" key ;\n"));
+        assert!(grammar.contains("separator = \":\" ;\n"));
+        assert!(grammar.contains("escape = \"\\\" ( '\"' | \"\\\" ) ;\n"));
+        assert!(!grammar.contains("comment"));
+
+        p.param_separators = Cow::Borrowed(&[':', '=']);
+        p.single_quotes = true;
+        p.comment = Some('#');
+        p.terminator = Some(';');
+
+        let grammar = p.grammar_ebnf();
+        assert!(grammar.starts_with("command = \"$\" name { space token } [ comment ] [ \";\" ] ;\n"));
+        assert!(grammar.contains("separator = \":\" | \"=\" ;\n"));
+        assert!(grammar.contains("escape = \"\\\" ( '\"' | \"'\" | \"\\\" ) ;\n"));
+        assert!(grammar.contains("comment = \"#\" { character } ;\n"));
+    }
+
+    #[test]
+    fn separator_in_value_test() {
+        let mut p = Parser::new('!', '-');
+
+        let command = p.parse(r#"!foo -time:12:30 -a:b:c -"x:y":z -q:"1:2" -e::"#).unwrap();
+        assert_eq!(command.parameters_sorted(), vec![
+            ("a", "b:c"), ("e", ":"), ("q", "1:2"), ("time", "12:30"), ("x:y", "z"),
+        ]);
+        assert_eq!(p.parse(command.to_string()).unwrap(), command);
+
+        p.param_separators = Cow::Borrowed(&[':', '=']);
+        let command = p.parse("!foo -url=a=b:c -t:1=2").unwrap();
+        assert_eq!(command.parameters_sorted(), vec![("t", "1=2"), ("url", "a=b:c")]);
+    }
+
+    #[test]
+    fn bare_key_value_test() {
+        let mut p = Parser::new('!', '-');
+
+        let command = p.parse("!run PORT=8080 plainarg").unwrap();
+        assert_eq!(command.arguments, vec!["PORT=8080", "plainarg"]);
+
+        p.bare_key_value = true;
+
+        let command = p.parse(r#"!run PORT=8080 plainarg HOST="local host" URL=a=b EMPTY= "Q=1" -k:v"#).unwrap();
+        assert_eq!(command.arguments, vec!["plainarg", "Q=1"]);
+        assert_eq!(command.parameters_sorted(), vec![
+            ("EMPTY", ""), ("HOST", "local host"), ("PORT", "8080"), ("URL", "a=b"), ("k", "v"),
+        ]);
+        assert_eq!(command.quote_styles.parameter("HOST"), QuoteStyle::Double);
+
+        // a key can't be empty
+        let command = p.parse("!run =x =a=b ==x").unwrap();
+        assert_eq!(command.arguments, vec!["=x", "=a=b", "==x"]);
+        assert!(command.parameters.is_empty());
+
+        let command = p.parse(r#"!run a\=b k\=ey=v\=al"#).unwrap();
+        assert_eq!(command.arguments, vec!["a=b"]);
+        assert_eq!(command.parameters_sorted(), vec![("k=ey", "v\\=al")]);
+
+        assert!(p.grammar_ebnf().contains("token = bare_parameter | argument | option | parameter ;\n"));
+
+        p.flags_only = true;
+        assert!(matches!(p.parse("!run PORT=8080"), Err(ParametersNotAllowed(Span { char_start: 9, .. }))));
+    }
+
+    #[test]
+    fn parse_flags_test() {
+        let p = Parser::new('!', '-');
+
+        let (options, parameters) = p.parse_flags(r#"  -verbose -"dry run" -level:3 -msg:"a b""#).unwrap();
+        assert_eq!(options, HashSet::from(["verbose".to_string(), "dry run".to_string()]));
+        assert_eq!(parameters, HashMap::from([
+            ("level".to_string(), "3".to_string()),
+            ("msg".to_string(), "a b".to_string()),
+        ]));
+
+        let (options, parameters) = p.parse_flags("").unwrap();
+        assert!(options.is_empty() && parameters.is_empty());
+
+        assert!(matches!(p.parse_flags("-opt ärg -key:val"), Err(UnexpectedArgument(Span { char_start: 5, byte_start: 5 }))));
+        assert!(matches!(p.parse_flags(r#"-ä "arg""#), Err(UnexpectedArgument(Span { char_start: 3, byte_start: 4 }))));
+        assert!(matches!(p.parse_flags("-msg:\"a b"), Err(UnterminatedQuote(Span { char_start: 5, .. }))));
+    }
+
+    #[test]
+    fn duplicate_parameter_test() {
+        let mut p = Parser::new('!', '-');
+
+        let command = p.parse("!foo -tag:a -tag:b").unwrap();
+        assert_eq!(command.parameters.get("tag"), Some(&"b".to_string()));
+
+        p.reject_duplicate_parameters = true;
+
+        let command = p.parse("!foo -tag:a -tag -other:b").unwrap();
+        assert_eq!(command.parameters.len(), 2);
+
+        let err = p.parse("!foo -tag:a arg -tag:b").unwrap_err();
+        assert!(matches!(&err, DuplicateParameter(Span { char_start: 16, byte_start: 16 }, key) if key == "tag"));
+        assert_eq!(err.to_string(), "duplicate parameter tag at position 16");
+
+        assert!(matches!(p.parse(r#"!foo -"a b":1 -"a b":"2" x"#), Err(DuplicateParameter(Span { char_start: 14, .. }, key)) if key == "a b"));
+        assert!(matches!(p.parse_flags("-k:1 -k:"), Err(DuplicateParameter(Span { char_start: 5, .. }, _))));
+    }
+
+    #[test]
+    fn case_insensitive_keys_test() {
+        let mut p = Parser::new('!', '-');
+
+        let command = p.parse("!foo -Name:Alice -name:Bob").unwrap();
+        assert_eq!(command.parameters.len(), 2);
+        assert_eq!(command.param_ci("name"), Some("Bob"));
+        assert!(matches!(command.param_ci("NAME"), Some("Alice" | "Bob")));
+
+        p.case_insensitive_keys = true;
+
+        let command = p.parse("!foo -name:Alice -Name:Bob -Opt -x:1").unwrap();
+        assert_eq!(command.parameters_sorted(), vec![("name", "Bob"), ("x", "1")]);
+        assert_eq!(command.key_casing.get("name"), Some(&"Name".to_string()));
+        assert!(!command.key_casing.contains_key("x"));
+        assert!(command.options.contains("Opt"));
+        assert_eq!(command.param_ci("NaMe"), Some("Bob"));
+        assert_eq!(command.to_string(), "!foo -Opt -Name:Bob -x:1");
+        assert_eq!(p.parse(command.to_string()).unwrap(), command);
+
+        let command = p.parse("!foo -Name:Alice -name:Bob").unwrap();
+        assert!(command.key_casing.is_empty());
+        assert_eq!(command.to_string(), "!foo -name:Bob");
+
+        p.reject_duplicate_parameters = true;
+        assert!(matches!(p.parse("!foo -name:Alice -NAME:Bob"), Err(DuplicateParameter(_, key)) if key == "name"));
+    }
+
+    #[test]
+    fn strict_separators_test() {
+        let mut p = Parser::new('!', '-');
+
+        assert_eq!(p.parse(r#"!foo"bar""#).unwrap().name, r#"foo"bar""#);
+        assert_eq!(p.parse("!foo-opt").unwrap().name, "foo-opt");
+
+        p.strict_separators = true;
+
+        assert!(matches!(p.parse(r#"!foo"bar""#), Err(NameError(Span { char_start: 4, .. }, '"'))));
+        assert!(matches!(p.parse("!foo-opt"), Err(NameError(Span { char_start: 4, .. }, '-'))));
+
+        let command = p.parse(r#"!foo "bar" -opt"#).unwrap();
+        assert_eq!(command.name, "foo");
+        assert_eq!(command.arguments, vec!["bar"]);
+        assert!(command.options.contains("opt"));
+        assert_eq!(p.parse(r#"!"foo bar" x"#).unwrap().name, "foo bar");
+    }
+
+    #[test]
+    fn explain_test() {
+        let p = Parser::new('!', '-');
+
+        let explanation = p.explain(r#"!deploy -env:prod "my app" -force v2 -note:"rush job""#).unwrap();
+        assert_eq!(
+            explanation,
+            "name: deploy; parameter env = prod; argument 1: 'my app'; option: force; argument 2: 'v2'; parameter note = rush job."
+        );
+
+        assert_eq!(p.explain("!foo").unwrap(), "name: foo.");
+        assert!(matches!(p.explain(r#"!foo "bar"#), Err(UnterminatedQuote(Span { char_start: 5, .. }))));
+    }
+
+    #[test]
+    fn parse_shallow_test() {
+        let p = Parser::new('!', '-');
+
+        for raw in [
+            "!foo", "!foo ", "!foo   arg", r#"!foo sub "long arg" -opt -key:"a b""#, r#"!"long name"-opt x"#,
+        ] {
+            let shallow = p.parse_shallow(raw).unwrap();
+            assert_eq!(shallow.parse_tail(&p).unwrap(), p.parse(raw).unwrap(), "{}", raw);
+        }
+
+        let shallow = p.parse_shallow(r#"!foo -key:"unterminated"#).unwrap();
+        assert_eq!(shallow.name, "foo");
+        assert!(matches!(shallow.parse_tail(&p), Err(UnterminatedQuote(Span { char_start: 5, .. }))));
+
+        assert!(matches!(p.parse_shallow("foo"), Err(PrefixError(Span { char_start: 0, .. }, 'f'))));
+
+        let mut p = Parser::new('!', '-');
+        p.strict = true;
+
+        for raw in [r#"!"a b""#, r#"!"a b" x"#, r#"!"a b"-opt"#, r#"!foo "x"-opt"#] {
+            let shallow = p.parse_shallow(raw).unwrap();
+            assert_eq!(shallow.parse_tail(&p).unwrap(), p.parse(raw).unwrap(), "{}", raw);
+        }
+
+        assert!(matches!(p.parse(r#"!"a b"x"#), Err(UnexpectedCharacter(Span { char_start: 6, .. }, 'x'))));
+        assert!(matches!(p.parse_shallow(r#"!"a b"x"#), Err(UnexpectedCharacter(Span { char_start: 6, .. }, 'x'))));
+    }
+
+    #[test]
+    fn parse_options_only_test() {
+        let p = Parser::new('!', '-');
+
+        let command = p.parse_options_only("-debug -level:3").unwrap();
+        assert_eq!(command, Command::new(
+            '!', '-', String::new(), vec![],
+            HashSet::from(["debug".to_string()]),
+            HashMap::from([("level".to_string(), "3".to_string())]),
+        ));
+
+        let command = p.parse_options_only(r#"  file "a b" -v"#).unwrap();
+        assert_eq!(command.arguments, vec!["file", "a b"]);
+        assert!(command.options.contains("v"));
+        assert_eq!(p.parse_options_only("").unwrap(), p.empty_command());
+
+        assert!(matches!(p.parse_options_only("  !foo -debug"), Err(UnexpectedPrefix(Span { char_start: 2, .. }))));
+        assert_eq!(p.parse_options_only("a!b").unwrap().arguments, vec!["a!b"]);
+    }
+
+    #[test]
+    fn key_path_test() {
+        let mut p = Parser::new('!', '-');
+        p.param_separators = Cow::Borrowed(&['=']);
+
+        let command = p.parse("!foo -a:b=1 -a:c:d=x:y -a:e -f=").unwrap();
+        assert_eq!(command.parameters_sorted(), vec![("a:b", "1"), ("a:c:d", "x:y"), ("f", "")]);
+        assert_eq!(command.options_sorted(), vec!["a:e"]);
+
+        let command = p.parse(r#"!foo -"a b:c"="d e""#).unwrap();
+        assert_eq!(command.parameters.get("a b:c"), Some(&"d e".to_string()));
+    }
+
+    #[test]
+    fn error_kind_test() {
+        let mut p = Parser::new('!', '-');
+        p.reject_duplicate_parameters = true;
+        p.max_token_len = Some(8);
+
+        let kind = |raw: &str| p.parse(raw).unwrap_err().kind();
+
+        assert_eq!(kind("foo"), ParseErrorKind::Prefix);
+        assert_eq!(kind("! foo"), ParseErrorKind::Name);
+        assert_eq!(kind(r#"!foo "\x""#), ParseErrorKind::Escape);
+        assert_eq!(kind(r#"!foo "\"#), ParseErrorKind::Escape);
+        assert_eq!(kind(r#"!foo "bar"#), ParseErrorKind::Quote);
+        assert_eq!(kind("!foo -k:1 -k:2"), ParseErrorKind::NotAllowed);
+        assert_eq!(kind("!foo abcdefghi"), ParseErrorKind::TooLong);
+        assert_eq!(kind(""), ParseErrorKind::Empty);
+        assert_eq!(p.parse_flags("-a b").unwrap_err().kind(), ParseErrorKind::Unexpected);
+
+        assert_eq!(p.parse("!foo -k:1 -k:2").unwrap_err().position(), Some(Span::new(10, 10)));
+        assert_eq!(p.parse("").unwrap_err().position(), None);
+    }
+
+    #[test]
+    fn arg_separator_test() {
+        let mut p = Parser::new('!', '-');
+        p.arg_separator = ',';
+
+        let command = p.parse(r#"!import a,b,"c,d",e f,,-opt,-k:"v,w",g"#).unwrap();
+        assert_eq!(command.name, "import");
+        assert_eq!(command.arguments, vec!["a", "b", "c,d", "e f", "g"]);
+        assert!(command.options.contains("opt"));
+        assert_eq!(command.parameters.get("k"), Some(&"v,w".to_string()));
+
+        let command = p.parse("!import  a , b").unwrap();
+        assert_eq!(command.arguments, vec![" a ", " b"]);
+
+        let (_, spans) = p.parse_with_spans("!import a,bc").unwrap();
+        assert_eq!(spans.arguments, vec![8..9, 10..12]);
+    }
+
+    #[test]
+    fn parse_exact_test() {
+        let mut p = Parser::new('!', '-');
+
+        assert_eq!(p.parse_exact("!foo a;b").unwrap().arguments, vec!["a;b"]);
+
+        p.terminator = Some(';');
+
+        assert_eq!(p.parse_exact("!foo a -b").unwrap(), p.parse("!foo a -b").unwrap());
+        assert_eq!(p.parse_exact("!foo a;  ").unwrap(), p.parse("!foo a").unwrap());
+        assert_eq!(p.parse_exact(r#"!foo "a;b""#).unwrap().arguments, vec!["a;b"]);
+
+        assert!(matches!(p.parse_exact("!foo a; junk"), Err(TrailingInput(Span { char_start: 8, .. }))));
+        assert!(matches!(p.parse_exact("!fö a;;"), Err(TrailingInput(Span { char_start: 6, byte_start: 7 }))));
+        assert!(matches!(p.parse_exact(r#"!foo "a; junk"#), Err(UnterminatedQuote(Span { char_start: 5, .. }))));
+    }
+
+    #[test]
+    fn parse_validate_test() {
+        let mut p = Parser::new('!', '-');
+
+        let errors = p.parse_validate(r#"!foo "a\qb c" ok -k:"d\ze" fine"#).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0], EscapeError(Span { char_start: 8, .. }, 'q')));
+        assert!(matches!(errors[1], EscapeError(Span { char_start: 23, .. }, 'z')));
+
+        assert_eq!(p.parse_validate("!foo a -b -k:v").unwrap(), p.parse("!foo a -b -k:v").unwrap());
+        assert!(matches!(p.parse_validate("foo").unwrap_err()[..], [PrefixError(..)]));
+
+        p.strict = true;
+        p.reject_duplicate_parameters = true;
+
+        let errors = p.parse_validate(r#"!foo "a"xyz -k:1 -k:2 -"b"c "d"#).unwrap_err();
+        assert_eq!(errors.len(), 4);
+        assert!(matches!(errors[0], UnexpectedCharacter(Span { char_start: 8, .. }, 'x')));
+        assert!(matches!(errors[1], DuplicateParameter(Span { char_start: 17, .. }, _)));
+        assert!(matches!(errors[2], UnexpectedCharacter(Span { char_start: 26, .. }, 'c')));
+        assert!(matches!(errors[3], UnterminatedQuote(Span { char_start: 28, .. })));
+    }
+
+    #[test]
+    fn raw_after_option_test() {
+        let mut p = Parser::new('!', '-');
+        p.raw_after_option = Some("raw".to_string());
+
+        let command = p.parse(r#"!say a -raw hello "world" -x"#).unwrap();
+        assert_eq!(command.arguments, vec!["a", r#"hello "world" -x"#]);
+        assert_eq!(command.options_sorted(), vec!["raw"]);
+
+        let command = p.parse(r#"!say -raw  "unterminated "#).unwrap();
+        assert_eq!(command.arguments, vec![r#" "unterminated "#]);
+
+        let command = p.parse("!say -raw").unwrap();
+        assert!(command.arguments.is_empty());
+        assert!(command.options.contains("raw"));
+
+        let command = p.parse("!say -rawer x -raw:1 y").unwrap();
+        assert_eq!(command.arguments, vec!["x", "y"]);
+
+        let (_, spans) = p.parse_with_spans("!say -raw a b").unwrap();
+        assert_eq!(spans.arguments, vec![10..13]);
+    }
+
+    #[test]
+    fn name_no_option_prefix_test() {
+        let mut p = Parser::new('!', '-');
+
+        assert_eq!(p.parse("!-foo arg").unwrap().name, "-foo");
+
+        p.name_no_option_prefix = true;
+
+        assert!(matches!(p.parse("!-foo arg"), Err(NameError(Span { char_start: 1, .. }, '-'))));
+        assert_eq!(p.parse("!foo-bar").unwrap().name, "foo-bar");
+        assert_eq!(p.parse(r#"!"-foo""#).unwrap().name, "-foo");
+    }
+
+    #[test]
+    fn arguments_iter_test() {
+        let p = Parser::new('!', '-');
+        let raw = format!("!foo first -opt \"second arg\" -k:v {} \"unterminated", "x ".repeat(1000));
+
+        let args: Vec<Cow<str>> = p.arguments_iter(&raw).take(2).collect::<Result<_, _>>().unwrap();
+        assert_eq!(args, vec!["first", "second arg"]);
+        assert!(matches!(args[0], Cow::Borrowed(_)));
+
+        assert_eq!(p.arguments_iter(&raw).filter(Result::is_ok).count(), 1002);
+        assert!(matches!(p.arguments_iter(&raw).last(), Some(Err(UnterminatedQuote(_)))));
+        assert!(matches!(p.arguments_iter("foo").next(), Some(Err(PrefixError(..)))));
+        assert_eq!(p.arguments_iter("!foo -opt").count(), 0);
+    }
+
+    #[test]
+    fn value_options_test() {
+        let mut p = Parser::new('!', '-');
+
+        let command = p.parse("!cp -o file.txt src").unwrap();
+        assert!(command.options.contains("o"));
+        assert_eq!(command.arguments, vec!["file.txt", "src"]);
+
+        p.value_options = vec!["o".to_string(), "name".to_string()];
+
+        let command = p.parse(r#"!cp -o file.txt src -name "a b" -v"#).unwrap();
+        assert_eq!(command.parameters_sorted(), vec![("name", "a b"), ("o", "file.txt")]);
+        assert_eq!(command.quote_styles.parameter("name"), QuoteStyle::Double);
+        assert_eq!(command.arguments, vec!["src"]);
+        assert!(command.options.contains("v"));
+
+        let command = p.parse("!cp -o -v -name -k:1 -o").unwrap();
+        assert_eq!(command.options_sorted(), vec!["name", "o", "v"]);
+        assert_eq!(command.parameters_sorted(), vec![("k", "1")]);
+
+        p.value_options = vec!["o".to_string(), "i".to_string()];
+
+        let command = p.parse("!cp -o -i in.txt out.txt").unwrap();
+        assert_eq!(command.options_sorted(), vec!["o"]);
+        assert_eq!(command.parameters_sorted(), vec![("i", "in.txt")]);
+        assert_eq!(command.arguments, vec!["out.txt"]);
+
+        let command = p.parse("!cp -o -i -o x").unwrap();
+        assert_eq!(command.options_sorted(), vec!["i", "o"]);
+        assert_eq!(command.parameters_sorted(), vec![("o", "x")]);
+
+        p.duplicate_option = DuplicateMode::Error;
+        assert!(matches!(p.parse("!cp -o -o"), Err(DuplicateOption(Span { char_start: 7, .. }, opt)) if opt == "o"));
+        assert!(p.parse("!cp -o a -o b").is_ok());
+        assert!(p.parse("!cp -o -o b").is_ok());
+        p.duplicate_option = DuplicateMode::KeepOne;
+
+        let (_, spans) = p.parse_with_spans("!cp -o file -v x").unwrap();
+        assert_eq!(spans.parameters["o"], 4..11);
+        assert_eq!(spans.options["v"], 12..14);
+        assert_eq!(spans.arguments, vec![15..16]);
+
+        assert!(matches!(p.parse(r#"!cp -o "file"#), Err(UnterminatedQuote(Span { char_start: 7, .. }))));
+    }
+
+    #[test]
+    fn parse_as_ref_test() {
+        let p = Parser::new('!', '-');
+        let expected = p.parse("!foo arg -opt").unwrap();
+
+        let owned = String::from("!foo arg -opt");
+        assert_eq!(p.parse(&owned).unwrap(), expected);
+        assert_eq!(p.parse(owned).unwrap(), expected);
+        assert_eq!(p.parse(Cow::Borrowed("!foo arg -opt")).unwrap(), expected);
+        assert_eq!(p.parse(Cow::<str>::Owned("!foo arg -opt".to_string())).unwrap(), expected);
+        assert_eq!(p.parse(Arc::<str>::from("!foo arg -opt")).unwrap(), expected);
+    }
+
+    #[test]
+    fn duplicate_option_test() {
+        let mut p = Parser::new('!', '-');
+
+        let command = p.parse("!foo -a -a").unwrap();
+        assert_eq!(command.options, HashSet::from(["a".to_string()]));
+        assert_eq!(command.repeated_option_level("a"), 1);
+
+        p.duplicate_option = DuplicateMode::Count;
+        let command = p.parse("!foo -a -a").unwrap();
+        assert_eq!(command.options, HashSet::from(["a".to_string()]));
+        assert_eq!(command.repeated_option_level("a"), 2);
+
+        p.duplicate_option = DuplicateMode::Error;
+        let err = p.parse("!foo -a -a").unwrap_err();
+        assert!(matches!(&err, DuplicateOption(span, opt) if span.char_start == 8 && opt == "a"));
+        assert_eq!(err.kind(), ParseErrorKind::NotAllowed);
+        assert!(p.parse("!foo -a -b").is_ok());
+    }
+
+    #[test]
+    fn entry_points_agree_test() {
+        let mut p = Parser::new('!', '-');
+        p.duplicate_option = DuplicateMode::Count;
+        p.case_insensitive_keys = true;
+
+        let raw = "!foo -v -Name:Alice -v arg";
+        let expected = p.parse(raw).unwrap();
+        assert_eq!(expected.repeated_option_level("v"), 2);
+        assert_eq!(expected.key_casing.get("name"), Some(&"Name".to_string()));
+
+        let commands = [
+            p.parse_one(raw).unwrap().0,
+            p.parse_exact(raw).unwrap(),
+            p.parse_with_rest(raw, 5).unwrap(),
+            p.parse_validate(raw).unwrap(),
+            p.parse_with_warnings(raw).unwrap().0,
+            p.parse_with_spans(raw).unwrap().0,
+            // the last token is only complete once something follows it
+            p.parse_prefix(&format!("{} ", raw)).command,
+            p.parse_shallow(raw).unwrap().parse_tail(&p).unwrap(),
+        ];
+
+        for command in commands {
+            assert_eq!(command, expected);
+            assert_eq!(command.option_counts, expected.option_counts);
+            assert_eq!(command.key_casing, expected.key_casing);
+        }
+    }
+
+    #[test]
+    fn try_parse_test() {
+        let p = Parser::new('!', '-');
+
+        assert_eq!(p.try_parse("just a normal sentence").unwrap(), None);
+        assert_eq!(p.try_parse("").unwrap(), None);
+
+        let command = p.try_parse("!foo arg1").unwrap().unwrap();
+        assert_eq!(command.name, "foo");
+        assert_eq!(command.arguments, vec!["arg1"]);
+
+        assert!(matches!(p.try_parse(r#"!foo "arg1\x""#), Err(EscapeError(Span { char_start: 11, .. }, 'x'))));
+        assert!(matches!(p.try_parse(r#"!foo "arg1"#), Err(UnterminatedQuote(Span { char_start: 5, .. }))));
+        assert!(matches!(p.try_parse(r#"!foo -key:"val"#), Err(UnterminatedQuote(Span { char_start: 10, .. }))));
     }
 
 }
@@ -0,0 +1,36 @@
+use crate::command::Command;
+use crate::error::ParseError;
+
+/// Kind of a token that hasn't been completed yet.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum PartialKind {
+    Name,
+    Argument,
+    /// An option, or the key of a parameter if a separator follows.
+    Option,
+    /// The value of the parameter with the given key.
+    ParameterValue(String),
+}
+
+/// A token at the end of a partially typed command.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct PartialToken {
+    pub kind: PartialKind,
+    /// Text of the token so far, with escape sequences resolved.
+    pub text: String,
+    /// The quote that has to be typed to complete the token.
+    ///
+    /// `None` if the token is completed by whitespace or the end of input.
+    pub closing_quote: Option<char>,
+}
+
+/// State of a partially typed command, returned by [`Parser::parse_prefix`](crate::Parser::parse_prefix).
+#[derive(Debug)]
+pub struct ParseProgress {
+    /// Everything that has been completely parsed so far.
+    pub command: Command,
+    /// The token the input ends in, `None` if the input ends between tokens.
+    pub partial: Option<PartialToken>,
+    /// The error parsing stopped at, if the input is already malformed.
+    pub error: Option<ParseError>,
+}
@@ -0,0 +1,100 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use crate::error::BoxedError;
+
+type Value = Box<dyn Any + Send + Sync>;
+type Coercer = Box<dyn Fn(&str) -> Result<Value, BoxedError> + Send + Sync>;
+
+/// Declares the types of the arguments and parameters of a command.
+///
+/// Used with [`Command::coerce`](crate::Command::coerce). Any type implementing [`FromStr`] can be used.
+///
+/// ```
+/// use command_parser::{Parser, Schema};
+///
+/// let schema = Schema::new()
+///     .arg::<u32>()
+///     .param::<f64>("scale");
+///
+/// let p = Parser::new('!', '-');
+/// let typed = p.parse("!resize 640 -scale:1.5").unwrap().coerce(&schema).unwrap();
+///
+/// assert_eq!(typed.arg::<u32>(0), Some(&640));
+/// assert_eq!(typed.param::<f64>("scale"), Some(&1.5));
+/// ```
+#[derive(Default)]
+pub struct Schema {
+    pub(crate) arguments: Vec<Coercer>,
+    pub(crate) parameters: HashMap<String, Coercer>,
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares the type of the next argument. Declared arguments are required.
+    pub fn arg<T>(mut self) -> Self
+    where
+        T: FromStr + Send + Sync + 'static,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        self.arguments.push(coercer::<T>());
+        self
+    }
+
+    /// Declares the type of the parameter `key`. Declared parameters are optional.
+    pub fn param<T>(mut self, key: impl Into<String>) -> Self
+    where
+        T: FromStr + Send + Sync + 'static,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        self.parameters.insert(key.into(), coercer::<T>());
+        self
+    }
+}
+
+impl fmt::Debug for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Schema")
+            .field("arguments", &self.arguments.len())
+            .field("parameters", &self.parameters.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+fn coercer<T>() -> Coercer
+where
+    T: FromStr + Send + Sync + 'static,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    Box::new(|s| {
+        s.parse::<T>()
+            .map(|val| Box::new(val) as Value)
+            .map_err(|e| Box::new(e) as BoxedError)
+    })
+}
+
+/// The values of a command converted to the types declared in a [`Schema`].
+///
+/// Created by [`Command::coerce`](crate::Command::coerce).
+#[derive(Debug, Default)]
+pub struct TypedCommand {
+    pub name: String,
+    pub(crate) arguments: Vec<Value>,
+    pub(crate) parameters: HashMap<String, Value>,
+}
+
+impl TypedCommand {
+    /// The argument at `index`, or `None` if it wasn't declared with type `T`.
+    pub fn arg<T: 'static>(&self, index: usize) -> Option<&T> {
+        self.arguments.get(index)?.downcast_ref()
+    }
+
+    /// The parameter `key`, or `None` if it is absent or wasn't declared with type `T`.
+    pub fn param<T: 'static>(&self, key: &str) -> Option<&T> {
+        self.parameters.get(key)?.downcast_ref()
+    }
+}
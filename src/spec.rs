@@ -0,0 +1,36 @@
+use std::collections::HashSet;
+
+/// Describes the expected shape of a [`Command`](crate::Command).
+///
+/// Used with [`Command::validate_against`](crate::Command::validate_against).
+///
+/// ```
+/// use std::collections::HashSet;
+/// use command_parser::{CommandSpec, Parser, ValidationError};
+///
+/// let spec = CommandSpec {
+///     required_params: vec!["to".to_string()],
+///     allowed_options: HashSet::from(["silent".to_string()]),
+///     min_args: 1,
+///     max_args: Some(1),
+/// };
+///
+/// let p = Parser::new('!', '-');
+///
+/// assert!(p.parse("!send hello -to:alice -silent").unwrap().validate_against(&spec).is_ok());
+/// assert!(matches!(
+///     p.parse("!send hello").unwrap().validate_against(&spec),
+///     Err(ValidationError::MissingParameter(_))
+/// ));
+/// ```
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct CommandSpec {
+    /// Keys of parameters that have to be present.
+    pub required_params: Vec<String>,
+    /// Options that may be present. Any other option is rejected.
+    pub allowed_options: HashSet<String>,
+    /// Minimum number of arguments.
+    pub min_args: usize,
+    /// Maximum number of arguments, `None` for no limit.
+    pub max_args: Option<usize>,
+}
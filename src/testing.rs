@@ -0,0 +1,135 @@
+//! Generators of random commands for property tests.
+//!
+//! The main invariant is that displaying a command and parsing the result gives an equal command:
+//!
+//! `parser.parse(&command.to_string()) == Ok(command)`
+//!
+//! New syntax should extend the generators so the invariant covers it.
+//!
+//! Tests can also count their allocations with [`allocations`].
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use crate::command::Command;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// The system allocator, counting allocations per thread so tests running in parallel don't interfere.
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Number of allocations made by the current thread while running `f`.
+pub(crate) fn allocations<F: FnOnce()>(f: F) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+/// Characters text is generated from, chosen to hit quoting, escaping and unicode handling.
+const ALPHABET: &[char] = &['a', 'b', 'Z', '0', ' ', '-', ':', '=', '"', '\'', '\\', '!', 'ä', '🤖'];
+
+/// A small deterministic xorshift generator, so failures can be reproduced from the seed.
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Rng {
+        Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns a number in `0..n`.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn text(&mut self, max_len: usize) -> String {
+        (0..self.below(max_len + 1)).map(|_| ALPHABET[self.below(ALPHABET.len())]).collect()
+    }
+}
+
+/// Generates an arbitrary valid command with `!` and `-` as prefixes.
+pub(crate) fn command(rng: &mut Rng) -> Command {
+    let mut name = String::new();
+
+    while name.is_empty() {
+        name = rng.text(6);
+    }
+
+    let arguments = (0..rng.below(4)).map(|_| rng.text(6)).collect();
+    let options: HashSet<String> = (0..rng.below(3)).map(|_| rng.text(4)).collect();
+    let parameters: HashMap<String, String> = (0..rng.below(3))
+        .map(|_| (rng.text(4), rng.text(6)))
+        .filter(|(key, _)| !options.contains(key))
+        .collect();
+
+    Command::new('!', '-', name, arguments, options, parameters)
+}
+
+/// Generates the string of an arbitrary valid command.
+pub(crate) fn command_string(rng: &mut Rng) -> String {
+    command(rng).to_string()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    const CASES: u64 = 2000;
+
+    #[test]
+    fn display_round_trip_test() {
+        let p = Parser::new('!', '-');
+
+        for seed in 0..CASES {
+            let command = command(&mut Rng::new(seed));
+            let displayed = command.to_string();
+
+            match p.parse(&displayed) {
+                Ok(parsed) => assert_eq!(parsed, command, "seed {} from {:?}", seed, displayed),
+                Err(e) => panic!("seed {} failed to parse {:?}: {}", seed, displayed, e),
+            }
+        }
+    }
+
+    #[test]
+    fn command_string_round_trip_test() {
+        let p = Parser::new('!', '-');
+
+        for seed in 0..CASES {
+            let raw = command_string(&mut Rng::new(seed));
+            let command = p.parse(&raw).unwrap();
+
+            // a dropped last token changes the string, for example if it isn't flushed at the end of input
+            assert_eq!(command.to_string(), raw, "seed {}", seed);
+        }
+    }
+}
@@ -0,0 +1,912 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::mem;
+use std::ops::Range;
+use std::str::CharIndices;
+use crate::error::{ParseError, Span};
+use crate::error::ParseError::{
+    DanglingEscape, DuplicateOption, DuplicateParameter, EmptyInput, EscapeError, NameError, ParametersNotAllowed, PrefixError, TokenTooLong, UnexpectedCharacter, UnterminatedQuote
+};
+use crate::command::QuoteStyle;
+use crate::parser::{DuplicateMode, Parser, UnknownEscape};
+use crate::progress::{PartialKind, PartialToken};
+
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum ParseState {
+    Prefix,
+    Name,
+    EscapeName,
+    LongName,
+    EscapeLongName,
+    Default,
+    Argument,
+    LongArgument,
+    EscapeLongArg,
+    QuoteEnd,
+    Option,
+    LongOption,
+    EscapeLongOption,
+    LongOptionEnd,
+    ParamConnector,
+    ParamVal,
+    ParamLongVal,
+    EscapeLongParamVal,
+}
+
+impl ParseState {
+    /// Whether characters in this state are outside of quotes, including the character that would start one.
+    fn is_outside_quotes(self) -> bool {
+        !matches!(
+            self,
+            ParseState::Prefix | ParseState::EscapeName |
+            ParseState::LongName | ParseState::EscapeLongName |
+            ParseState::LongArgument | ParseState::EscapeLongArg |
+            ParseState::LongOption | ParseState::EscapeLongOption |
+            ParseState::ParamLongVal | ParseState::EscapeLongParamVal
+        )
+    }
+}
+
+/// A single part of a command produced by a [`Tokenizer`].
+///
+/// Text is borrowed from the input where possible.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub(crate) enum Token<'a> {
+    Name(Cow<'a, str>),
+    Argument(Cow<'a, str>, QuoteStyle),
+    Option(Cow<'a, str>),
+    Parameter(Cow<'a, str>, Cow<'a, str>, QuoteStyle),
+}
+
+type TokenResult<'a> = Result<Token<'a>, ParseError>;
+
+/// Text of the token that is currently being parsed.
+///
+/// As long as the token is a contiguous slice of the input only its byte range is tracked.
+/// The text is copied once something, like an escape sequence, breaks that.
+#[derive(Debug, Default)]
+struct Buffer {
+    start: usize,
+    end: usize,
+    owned: Option<String>,
+}
+
+impl Buffer {
+    /// Pushes `c`, which is found at byte `pos` of `raw`.
+    fn push(&mut self, raw: &str, pos: usize, c: char) {
+        match &mut self.owned {
+            Some(owned) => {
+                owned.push(c);
+            }
+            None if self.start == self.end => {
+                self.start = pos;
+                self.end = pos + c.len_utf8();
+            }
+            None if self.end == pos => {
+                self.end += c.len_utf8();
+            }
+            None => {
+                let mut owned = raw[self.start..self.end].to_string();
+                owned.push(c);
+                self.owned = Some(owned);
+            }
+        }
+    }
+
+    fn as_str<'a>(&'a self, raw: &'a str) -> &'a str {
+        match &self.owned {
+            Some(owned) => owned,
+            None => &raw[self.start..self.end],
+        }
+    }
+
+    /// Length of the text in bytes.
+    fn len(&self) -> usize {
+        match &self.owned {
+            Some(owned) => owned.len(),
+            None => self.end - self.start,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match &self.owned {
+            Some(owned) => owned.is_empty(),
+            None => self.start == self.end,
+        }
+    }
+
+    /// Removes the last character of `raw` that was pushed.
+    fn pop(&mut self, raw: &str) -> Option<char> {
+        match &mut self.owned {
+            Some(owned) => owned.pop(),
+            None => {
+                let c = raw[self.start..self.end].chars().next_back()?;
+                self.end -= c.len_utf8();
+                Some(c)
+            }
+        }
+    }
+
+    fn take<'a>(&mut self, raw: &'a str) -> Cow<'a, str> {
+        let buffer = mem::take(self);
+
+        match buffer.owned {
+            Some(owned) => Cow::Owned(owned),
+            None => Cow::Borrowed(&raw[buffer.start..buffer.end]),
+        }
+    }
+}
+
+/// Runs the state machine of a [`Parser`] over a string, yielding each [`Token`] once it's complete.
+///
+/// After an error has been yielded the tokenizer is exhausted.
+pub(crate) struct Tokenizer<'p, 'a> {
+    parser: &'p Parser,
+    raw: &'a str,
+    chars: CharIndices<'a>,
+    cursor: usize,
+    /// Byte offset of the character that is currently processed.
+    byte: usize,
+    state: ParseState,
+    buffer: Buffer,
+    key_buffer: Cow<'a, str>,
+    quote_start: Span,
+    quote: char,
+    style: QuoteStyle,
+    /// Number of brackets opened but not closed in the current quoted value.
+    bracket_depth: usize,
+    finished: bool,
+    /// Char index where the current token starts.
+    token_start: usize,
+    /// Char range of the token returned last.
+    pub(crate) token_span: Range<usize>,
+    /// Number of chars up to and including the terminator, if one was found.
+    pub(crate) terminated_at: Option<usize>,
+    /// Parameter keys seen so far, only tracked with [`Parser::reject_duplicate_parameters`].
+    keys: HashSet<Cow<'a, str>>,
+    /// Options seen so far, only tracked with [`DuplicateMode::Error`].
+    options: HashSet<String>,
+    /// Key of the parameter returned last as it was written, if it was lowercased.
+    pub(crate) original_key: Option<String>,
+    /// Whether the rest of the input is one argument, after [`Parser::raw_after_option`] was found.
+    raw_rest: bool,
+    /// Whether the current unquoted argument starts with `=`, so it's never split by [`Parser::bare_key_value`].
+    leading_equals: bool,
+    /// Token read ahead while looking for the value of one of the [`Parser::value_options`], with its span.
+    pending: Option<(Option<TokenResult<'a>>, Range<usize>)>,
+}
+
+impl<'p, 'a> Tokenizer<'p, 'a> {
+    pub(crate) fn new(parser: &'p Parser, raw: &'a str) -> Tokenizer<'p, 'a> {
+        Tokenizer {
+            parser,
+            raw,
+            chars: raw.char_indices(),
+            cursor: 0,
+            byte: 0,
+            state: ParseState::Prefix,
+            buffer: Buffer::default(),
+            key_buffer: Cow::Borrowed(""),
+            quote_start: Span::default(),
+            quote: '"',
+            style: QuoteStyle::Unquoted,
+            bracket_depth: 0,
+            finished: false,
+            token_start: 0,
+            token_span: 0..0,
+            terminated_at: None,
+            keys: HashSet::new(),
+            options: HashSet::new(),
+            original_key: None,
+            raw_rest: false,
+            leading_equals: false,
+            pending: None,
+        }
+    }
+
+    /// Creates a tokenizer that starts directly at the name, for input that has its prefix stripped already.
+    pub(crate) fn without_prefix(parser: &'p Parser, raw: &'a str) -> Tokenizer<'p, 'a> {
+        Tokenizer {
+            state: ParseState::Name,
+            ..Tokenizer::new(parser, raw)
+        }
+    }
+
+    /// Creates a tokenizer that starts between tokens, for input without prefix and name.
+    pub(crate) fn fragment(parser: &'p Parser, raw: &'a str) -> Tokenizer<'p, 'a> {
+        Tokenizer {
+            state: ParseState::Default,
+            ..Tokenizer::new(parser, raw)
+        }
+    }
+
+    /// The part of the input that hasn't been consumed yet.
+    pub(crate) fn rest(&self) -> &'a str {
+        self.chars.as_str()
+    }
+
+    /// Fails like [`Parser::strict`] would if the next character can't follow a closing quote,
+    /// without consuming it.
+    pub(crate) fn check_quote_end(&self) -> Result<(), ParseError> {
+        if let (ParseState::QuoteEnd, Some((byte, c))) = (self.state, self.chars.clone().next()) {
+            if !self.is_separator(c) && c != self.parser.option_prefix {
+                return Err(UnexpectedCharacter(Span::new(self.cursor, byte), c));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn push(&mut self, c: char) {
+        self.buffer.push(self.raw, self.byte, c);
+    }
+
+    fn take_buffer(&mut self) -> Cow<'a, str> {
+        self.style = QuoteStyle::Unquoted;
+        self.buffer.take(self.raw)
+    }
+
+    fn take_argument(&mut self) -> Token<'a> {
+        let style = self.style;
+        Token::Argument(self.take_buffer(), style)
+    }
+
+    fn take_parameter(&mut self) -> Token<'a> {
+        let style = self.style;
+        Token::Parameter(mem::take(&mut self.key_buffer), self.take_buffer(), style)
+    }
+
+    /// Takes an unquoted option, splitting it into name and value if it has a short value like `-n5`.
+    fn take_option(&mut self) -> Result<Token<'a>, ParseError> {
+        let opt = self.take_buffer();
+
+        if self.parser.short_option_values && !self.parser.long_options.iter().any(|long| *long == opt) {
+            if let Some(c) = opt.chars().next() {
+                if opt.len() > c.len_utf8() {
+                    if self.parser.flags_only {
+                        // the value starts after the option prefix and the name
+                        return Err(ParametersNotAllowed(self.span_at(self.token_start + 2)));
+                    }
+
+                    let (key, val) = split_cow(opt, c.len_utf8());
+                    return Ok(Token::Parameter(key, val, QuoteStyle::Unquoted));
+                }
+            }
+        }
+
+        Ok(Token::Option(opt))
+    }
+
+    /// Whether `token` is dropped instead of being returned.
+    fn is_ignored(&self, token: &Token) -> bool {
+        matches!(token, Token::Option(opt) if opt.is_empty() && self.parser.ignore_empty_options)
+    }
+
+    /// Position of the character at `cursor`, which has to be the current one.
+    fn span(&self, cursor: usize) -> Span {
+        Span::new(cursor, self.byte)
+    }
+
+    /// Position of the character at any char index of the input.
+    pub(crate) fn span_at(&self, char_index: usize) -> Span {
+        let byte = self.raw.char_indices().nth(char_index).map_or(self.raw.len(), |(byte, _)| byte);
+        Span::new(char_index, byte)
+    }
+
+    /// Lowercases the key of a parameter with [`Parser::case_insensitive_keys`]
+    /// and fails if the parameter or option was seen before and duplicates are rejected.
+    fn check_token(&mut self, token: &mut Token<'a>) -> Result<(), ParseError> {
+        self.original_key = None;
+
+        if let Token::Parameter(key, _, _) = token {
+            if self.parser.case_insensitive_keys && key.chars().any(char::is_uppercase) {
+                let lowercase = Cow::Owned(key.to_lowercase());
+                self.original_key = Some(mem::replace(key, lowercase).into_owned());
+            }
+
+            if self.parser.reject_duplicate_parameters && !self.keys.insert(key.clone()) {
+                return Err(DuplicateParameter(self.span_at(self.token_span.start), key.to_string()));
+            }
+        }
+
+        match token {
+            // a value option is only checked once it's known whether it takes a value
+            Token::Option(opt) if !self.is_value_option(opt) => self.check_option(opt),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails if the option `opt` was seen before and duplicates are rejected.
+    fn check_option(&mut self, opt: &str) -> Result<(), ParseError> {
+        if self.parser.duplicate_option == DuplicateMode::Error && !self.options.insert(opt.to_string()) {
+            return Err(DuplicateOption(self.span_at(self.token_span.start), opt.to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn is_value_option(&self, opt: &str) -> bool {
+        self.parser.value_options.iter().any(|name| name == opt)
+    }
+
+    fn is_space(&self, c: char) -> bool {
+        (self.parser.is_whitespace)(c)
+    }
+
+    /// Whether `c` separates the tokens after the name, see [`Parser::arg_separator`].
+    fn is_separator(&self, c: char) -> bool {
+        match self.parser.arg_separator {
+            ' ' => self.is_space(c),
+            separator => c == separator,
+        }
+    }
+
+    fn is_quote(&self, c: char) -> bool {
+        c == '"' || (c == '\'' && self.parser.single_quotes)
+    }
+
+    fn is_escapable(&self, c: char) -> bool {
+        c == '\\' || self.is_quote(c)
+    }
+
+    fn open_quote(&mut self, cursor: usize, c: char, state: ParseState) {
+        self.quote_start = self.span(cursor);
+        self.quote = c;
+        self.state = state;
+        self.bracket_depth = 0;
+        self.style = if c == '\'' { QuoteStyle::Single } else { QuoteStyle::Double };
+    }
+
+    /// Pushes the character following a `\` inside quotes.
+    fn escape(&mut self, cursor: usize, c: char) -> Result<(), ParseError> {
+        if !self.is_escapable(c) {
+            match self.parser.unknown_escape {
+                UnknownEscape::Error => { return Err(EscapeError(self.span(cursor), c)); }
+                UnknownEscape::Keep => { self.buffer.push(self.raw, self.byte - 1, '\\'); }
+                UnknownEscape::Strip => {}
+            }
+        }
+
+        self.push(c);
+        Ok(())
+    }
+
+    /// Counts brackets inside quotes if [`Parser::balanced_brackets`] is set.
+    ///
+    /// Returns whether `c` has to be pushed as a literal character,
+    /// which is the case for brackets and for quotes inside brackets.
+    fn is_bracketed(&mut self, c: char) -> bool {
+        if !self.parser.balanced_brackets {
+            return false;
+        }
+
+        match c {
+            '{' | '[' => { self.bracket_depth += 1; }
+            '}' | ']' if self.bracket_depth > 0 => { self.bracket_depth -= 1; }
+            x if x == self.quote && self.bracket_depth > 0 => {}
+            _ => { return false; }
+        }
+
+        true
+    }
+
+    /// State to continue in after a closing quote.
+    fn after_quote(&self) -> ParseState {
+        if self.parser.strict {
+            ParseState::QuoteEnd
+        } else {
+            ParseState::Default
+        }
+    }
+
+    /// Feeds a single character into the state machine, keeping track of where tokens start and end.
+    fn step(&mut self, cursor: usize, c: char) -> Result<Option<Token<'a>>, ParseError> {
+        let previous = self.state;
+
+        if matches!(previous, ParseState::Prefix | ParseState::Default | ParseState::QuoteEnd) {
+            self.token_start = cursor;
+        }
+
+        let start = self.token_start;
+        let mut token = self.transition(cursor, c)?.filter(|token| !self.is_ignored(token));
+
+        if self.parser.max_token_len.is_some_and(|max| self.buffer.len() > max) {
+            return Err(TokenTooLong(self.span(cursor)));
+        }
+
+        if token.is_some() {
+            // a token ends before the space or character that terminates it, but includes its closing quote
+            let end = if self.is_space(c) || self.is_separator(c) || matches!(previous, ParseState::LongOptionEnd) {
+                cursor
+            } else {
+                cursor + 1
+            };
+
+            self.token_span = start..end;
+        }
+
+        if let Some(token) = &mut token {
+            self.check_token(token)?;
+
+            if let Token::Option(opt) = token {
+                self.raw_rest = self.parser.raw_after_option.as_deref() == Some(opt);
+            }
+        }
+
+        Ok(token)
+    }
+
+    fn transition(&mut self, cursor: usize, c: char) -> Result<Option<Token<'a>>, ParseError> {
+        match self.state {
+            ParseState::Prefix => {
+                match c {
+                    x if x == self.parser.prefix => {
+                        self.state = ParseState::Name;
+                    }
+                    _ => { return Err(PrefixError(self.span(cursor), c)); }
+                }
+            }
+            ParseState::Name => {
+                match c {
+                    x if x == self.parser.prefix && self.parser.collapse_repeated_prefix && self.buffer.is_empty() => {}
+                    x if self.is_quote(x) && self.buffer.is_empty() => {
+                        self.open_quote(cursor, c, ParseState::LongName);
+                    }
+                    '\\' if self.parser.escape_in_name => {
+                        self.state = ParseState::EscapeName;
+                    }
+                    x if x == self.parser.option_prefix && self.parser.name_no_option_prefix && self.buffer.is_empty() => {
+                        return Err(NameError(self.span(cursor), c));
+                    }
+                    x if self.parser.strict_separators && (self.is_quote(x) || x == self.parser.option_prefix) => {
+                        return Err(NameError(self.span(cursor), c));
+                    }
+                    x if self.is_space(x) && self.parser.trim_name && self.buffer.is_empty() => {}
+                    x if self.is_space(x) => {
+                        if self.buffer.is_empty() {
+                            return Err(NameError(self.span(cursor), c));
+                        } else {
+                            self.state = ParseState::Default;
+                            return Ok(Some(Token::Name(self.take_buffer())));
+                        }
+                    }
+                    _ => { self.push(c); }
+                }
+            }
+            ParseState::LongName => {
+                match c {
+                    x if x == self.quote => {
+                        if self.buffer.is_empty() {
+                            return Err(NameError(self.span(cursor), c));
+                        }
+
+                        self.state = self.after_quote();
+                        return Ok(Some(Token::Name(self.take_buffer())));
+                    }
+                    '\\' => {
+                        self.state = ParseState::EscapeLongName;
+                    }
+                    _ => {
+                        self.push(c);
+                    }
+                }
+            }
+            ParseState::EscapeName => {
+                self.escape(cursor, c)?;
+                self.state = ParseState::Name;
+            }
+            ParseState::EscapeLongName => {
+                self.escape(cursor, c)?;
+                self.state = ParseState::LongName;
+            }
+            ParseState::Argument => {
+                match c {
+                    x if self.is_separator(x) => {
+                        self.state = ParseState::Default;
+                        return Ok(Some(self.take_argument()));
+                    }
+                    '=' if self.parser.bare_key_value && self.style == QuoteStyle::Unquoted
+                        && self.buffer.as_str(self.raw).ends_with('\\') => {
+                        // `\=` is an `=` that doesn't split the argument
+                        self.buffer.pop(self.raw);
+                        self.push(c);
+                    }
+                    '=' if self.parser.bare_key_value && self.style == QuoteStyle::Unquoted && !self.leading_equals => {
+                        if self.parser.flags_only {
+                            return Err(ParametersNotAllowed(self.span(cursor)));
+                        }
+
+                        self.key_buffer = self.take_buffer();
+                        self.state = ParseState::ParamConnector;
+                    }
+                    x if self.parser.concat_fragments && self.is_quote(x) => {
+                        self.open_quote(cursor, c, ParseState::LongArgument);
+                    }
+                    _ => {
+                        self.push(c);
+                    }
+                }
+            }
+            ParseState::LongArgument => {
+                if self.is_bracketed(c) {
+                    self.push(c);
+                    return Ok(None);
+                }
+
+                match c {
+                    x if x == self.quote && self.parser.concat_fragments => {
+                        self.state = ParseState::Argument;
+                    }
+                    x if x == self.quote => {
+                        self.state = self.after_quote();
+                        return Ok(Some(self.take_argument()));
+                    }
+                    '\\' => {
+                        self.state = ParseState::EscapeLongArg;
+                    }
+                    _ => {
+                        self.push(c);
+                    }
+                }
+            }
+            ParseState::EscapeLongArg => {
+                self.escape(cursor, c)?;
+                self.state = ParseState::LongArgument;
+            }
+            ParseState::Option => {
+                match c {
+                    x if x == self.parser.option_prefix && self.parser.collapse_option_prefix && self.buffer.is_empty() => {}
+                    x if self.is_separator(x) => {
+                        self.state = ParseState::Default;
+                        return self.take_option().map(Some);
+                    }
+                    x if self.parser.param_separators.contains(&x) => {
+                        if self.parser.flags_only {
+                            return Err(ParametersNotAllowed(self.span(cursor)));
+                        }
+
+                        self.key_buffer = self.take_buffer();
+                        self.state = ParseState::ParamConnector;
+                    }
+                    x if self.is_quote(x) && self.buffer.is_empty() => {
+                        self.open_quote(cursor, c, ParseState::LongOption);
+                    }
+                    _ => {
+                        self.push(c);
+                    }
+                }
+            }
+            ParseState::LongOption => {
+                match c {
+                    x if x == self.quote => {
+                        self.state = ParseState::LongOptionEnd;
+                    }
+                    '\\' => {
+                        self.state = ParseState::EscapeLongOption;
+                    }
+                    _ => {
+                        self.push(c);
+                    }
+                }
+            }
+            ParseState::EscapeLongOption => {
+                self.escape(cursor, c)?;
+                self.state = ParseState::LongOption;
+            }
+            ParseState::LongOptionEnd => {
+                match c {
+                    x if self.parser.param_separators.contains(&x) => {
+                        if self.parser.flags_only {
+                            return Err(ParametersNotAllowed(self.span(cursor)));
+                        }
+
+                        self.key_buffer = self.take_buffer();
+                        self.state = ParseState::ParamConnector;
+                    }
+                    _ => {
+                        // not a parameter key, so the quoted token is an option with spaces
+                        let option = Token::Option(self.take_buffer());
+                        self.state = self.after_quote();
+                        self.step(cursor, c)?;
+                        return Ok(Some(option));
+                    }
+                }
+            }
+            ParseState::QuoteEnd => {
+                match c {
+                    x if self.is_separator(x) => {
+                        self.state = ParseState::Default;
+                    }
+                    x if x == self.parser.option_prefix => {
+                        self.state = ParseState::Option;
+                    }
+                    _ => {
+                        return Err(UnexpectedCharacter(self.span(cursor), c));
+                    }
+                }
+            }
+            ParseState::ParamConnector => {
+                match c {
+                    x if self.is_quote(x) => {
+                        self.open_quote(cursor, c, ParseState::ParamLongVal);
+                    }
+                    x if self.is_separator(x) => {
+                        self.state = ParseState::Default;
+                        return Ok(Some(self.take_parameter()));
+                    }
+                    _ => {
+                        self.state = ParseState::ParamVal;
+                        self.push(c);
+                    }
+                }
+            }
+            ParseState::ParamVal => {
+                match c {
+                    x if self.is_separator(x) => {
+                        self.state = ParseState::Default;
+                        return Ok(Some(self.take_parameter()));
+                    }
+                    x if self.parser.concat_fragments && self.is_quote(x) => {
+                        self.open_quote(cursor, c, ParseState::ParamLongVal);
+                    }
+                    _ => {
+                        self.push(c);
+                    }
+                }
+            }
+            ParseState::ParamLongVal => {
+                if self.is_bracketed(c) {
+                    self.push(c);
+                    return Ok(None);
+                }
+
+                match c {
+                    x if x == self.quote && self.parser.concat_fragments => {
+                        self.state = ParseState::ParamVal;
+                    }
+                    x if x == self.quote => {
+                        self.state = self.after_quote();
+                        return Ok(Some(self.take_parameter()));
+                    }
+                    '\\' => {
+                        self.state = ParseState::EscapeLongParamVal;
+                    }
+                    _ => {
+                        self.push(c);
+                    }
+                }
+            }
+            ParseState::EscapeLongParamVal => {
+                self.escape(cursor, c)?;
+                self.state = ParseState::ParamLongVal;
+            }
+            ParseState::Default => {
+                match c {
+                    x if self.is_separator(x) => {}
+                    x if self.is_quote(x) => {
+                        self.open_quote(cursor, c, ParseState::LongArgument);
+                    }
+                    x if x == self.parser.option_prefix => {
+                        self.state = ParseState::Option;
+                    }
+                    _ => {
+                        self.state = ParseState::Argument;
+                        self.leading_equals = c == '=';
+                        self.push(c);
+                    }
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Feeds characters into the state machine until a token is complete.
+    ///
+    /// Unlike [`next`](Iterator::next) this returns `None` at the end of input
+    /// without flushing the token that is still in progress.
+    pub(crate) fn advance(&mut self) -> Option<Result<Token<'a>, ParseError>> {
+        if self.finished {
+            return None;
+        }
+
+        if self.raw_rest {
+            let rest = self.rest();
+            let start = self.cursor;
+
+            self.raw_rest = false;
+            self.chars = "".char_indices();
+            self.cursor += rest.chars().count();
+            self.token_span = start..self.cursor;
+
+            if !rest.is_empty() {
+                return Some(Ok(Token::Argument(Cow::Borrowed(rest), QuoteStyle::Unquoted)));
+            }
+        }
+
+        while let Some((byte, c)) = self.chars.next() {
+            let cursor = self.cursor;
+            self.cursor += 1;
+            self.byte = byte;
+
+            if self.parser.comment == Some(c) && self.state.is_outside_quotes() {
+                self.chars = "".char_indices();
+                break;
+            }
+
+            if self.parser.terminator == Some(c) && self.state.is_outside_quotes() {
+                self.terminated_at = Some(self.cursor);
+                self.chars = "".char_indices();
+                break;
+            }
+
+            match self.step(cursor, c) {
+                Ok(None) => {}
+                Ok(Some(token)) => return Some(Ok(token)),
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Describes the token that is still in progress, if there is one.
+    pub(crate) fn partial(&self) -> Option<PartialToken> {
+        let (kind, closing_quote) = match self.state {
+            ParseState::Name | ParseState::EscapeName => (PartialKind::Name, None),
+            ParseState::LongName | ParseState::EscapeLongName => (PartialKind::Name, Some(self.quote)),
+            ParseState::Argument => (PartialKind::Argument, None),
+            ParseState::LongArgument | ParseState::EscapeLongArg => (PartialKind::Argument, Some(self.quote)),
+            ParseState::Option | ParseState::LongOptionEnd => (PartialKind::Option, None),
+            ParseState::LongOption | ParseState::EscapeLongOption => (PartialKind::Option, Some(self.quote)),
+            ParseState::ParamConnector | ParseState::ParamVal => {
+                (PartialKind::ParameterValue(self.key_buffer.to_string()), None)
+            }
+            ParseState::ParamLongVal | ParseState::EscapeLongParamVal => {
+                (PartialKind::ParameterValue(self.key_buffer.to_string()), Some(self.quote))
+            }
+            ParseState::Prefix | ParseState::Default | ParseState::QuoteEnd => return None,
+        };
+
+        Some(PartialToken {
+            kind,
+            text: self.buffer.as_str(self.raw).to_string(),
+            closing_quote,
+        })
+    }
+
+    /// Continues after an error by dropping the current token and skipping to the next separator.
+    ///
+    /// Nothing is skipped if the character that caused the error is a separator itself.
+    pub(crate) fn recover(&mut self) {
+        let failed_on_separator = self.raw[self.byte..].chars().next().is_some_and(|c| self.is_separator(c));
+
+        if !failed_on_separator {
+            while let Some((byte, c)) = self.chars.next() {
+                self.cursor += 1;
+                self.byte = byte;
+
+                if self.is_separator(c) {
+                    break;
+                }
+            }
+        }
+
+        self.state = ParseState::Default;
+        self.buffer = Buffer::default();
+        self.key_buffer = Cow::Borrowed("");
+        self.style = QuoteStyle::Unquoted;
+        self.finished = false;
+    }
+
+    /// Returns the next token, without looking for the values of [`Parser::value_options`].
+    fn next_token(&mut self) -> Option<TokenResult<'a>> {
+        if self.finished {
+            return None;
+        }
+
+        let token = self.advance();
+
+        if token.is_none() {
+            self.finished = true;
+
+            return match self.finish() {
+                Ok(Some(token)) if self.is_ignored(&token) => None,
+                Ok(Some(mut token)) => Some(self.check_token(&mut token).map(|_| token)),
+                result => result.transpose(),
+            };
+        }
+
+        token
+    }
+
+    /// Flushes whatever token is still in progress once the input is exhausted.
+    fn finish(&mut self) -> Result<Option<Token<'a>>, ParseError> {
+        self.token_span = self.token_start..self.cursor;
+
+        match self.state {
+            ParseState::Name => {
+                Ok(Some(Token::Name(self.take_buffer())))
+            }
+            ParseState::Argument => {
+                Ok(Some(self.take_argument()))
+            }
+            ParseState::Option => {
+                self.take_option().map(Some)
+            }
+            ParseState::LongOptionEnd => {
+                Ok(Some(Token::Option(self.take_buffer())))
+            }
+            ParseState::ParamConnector | ParseState::ParamVal => {
+                Ok(Some(self.take_parameter()))
+            }
+            ParseState::EscapeName | ParseState::EscapeLongName | ParseState::EscapeLongArg |
+            ParseState::EscapeLongOption | ParseState::EscapeLongParamVal => {
+                // the `\` is the last character of the input
+                Err(DanglingEscape(Span::new(self.cursor - 1, self.raw.len() - 1)))
+            }
+            ParseState::LongName | ParseState::LongArgument |
+            ParseState::LongOption | ParseState::ParamLongVal => {
+                Err(UnterminatedQuote(self.quote_start))
+            }
+            // nothing at all was consumed, so there is no prefix either
+            ParseState::Prefix => Err(EmptyInput),
+            ParseState::Default | ParseState::QuoteEnd => Ok(None),
+        }
+    }
+}
+
+fn split_cow(cow: Cow<'_, str>, mid: usize) -> (Cow<'_, str>, Cow<'_, str>) {
+    match cow {
+        Cow::Borrowed(s) => (Cow::Borrowed(&s[..mid]), Cow::Borrowed(&s[mid..])),
+        Cow::Owned(s) => (Cow::Owned(s[..mid].to_string()), Cow::Owned(s[mid..].to_string())),
+    }
+}
+
+impl<'a> Iterator for Tokenizer<'_, 'a> {
+    type Item = Result<Token<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = match self.pending.take() {
+            Some((token, span)) => {
+                self.token_span = span;
+                token
+            }
+            None => self.next_token(),
+        };
+
+        match token {
+            Some(Ok(Token::Option(opt))) if self.is_value_option(&opt) => {
+                let span = self.token_span.clone();
+
+                match self.next_token() {
+                    Some(Ok(Token::Argument(val, style))) => {
+                        self.token_span = span.start..self.token_span.end;
+
+                        let mut token = Token::Parameter(opt, val, style);
+                        let result = self.check_token(&mut token).map(|_| token);
+                        self.finished |= result.is_err();
+                        Some(result)
+                    }
+                    next => {
+                        // no value, so it stays an option
+                        let next_span = self.token_span.clone();
+                        self.token_span = span;
+
+                        if let Err(err) = self.check_option(&opt) {
+                            self.finished = true;
+                            return Some(Err(err));
+                        }
+
+                        self.pending = Some((next, next_span));
+                        Some(Ok(Token::Option(opt)))
+                    }
+                }
+            }
+            token => token,
+        }
+    }
+}
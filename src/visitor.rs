@@ -0,0 +1,13 @@
+/// Receives the parts of a command as they are parsed by [`Parser::parse_visit`](crate::Parser::parse_visit).
+///
+/// Methods are called in the order the parts appear in the input.
+/// All of them do nothing by default, so only the interesting ones have to be implemented.
+pub trait ParseVisitor {
+    fn on_name(&mut self, _name: &str) {}
+
+    fn on_argument(&mut self, _arg: &str) {}
+
+    fn on_option(&mut self, _opt: &str) {}
+
+    fn on_parameter(&mut self, _key: &str, _val: &str) {}
+}
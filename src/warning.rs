@@ -0,0 +1,22 @@
+use std::fmt;
+
+/// A suspicious but valid pattern found by [`Parser::parse_with_warnings`](crate::Parser::parse_with_warnings).
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Warning {
+    /// The option prefix was followed by a space, as in `- opt`, which results in an empty option.
+    EmptyOption,
+    /// A parameter has an empty value, as in `-key:`.
+    EmptyParameterValue(String),
+    /// An option was given more than once.
+    DuplicateOption(String),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::EmptyOption => write!(f, "empty option"),
+            Warning::EmptyParameterValue(key) => write!(f, "empty value for parameter {}", key),
+            Warning::DuplicateOption(option) => write!(f, "duplicate option {}", option),
+        }
+    }
+}